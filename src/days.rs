@@ -9,7 +9,124 @@ pub mod day8;
 pub mod day9;
 
 use clap::Clap;
-use std::fs;
+use std::{
+    fs,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::intcode::{
+    limits::Limits, patch::Patch, profile::Profile, replay::IoLog, Cell, IntcodeComputer,
+};
+
+// limits, profiling and recording for the Intcode machines a day runs
+#[derive(Clap)]
+pub struct IntcodeOpts {
+    /// stop a machine after this many instructions
+    #[clap(long = "max-steps")]
+    max_steps: Option<u64>,
+    /// stop a machine after this many seconds
+    #[clap(long = "timeout")]
+    timeout: Option<f64>,
+    /// stop a machine that keeps coming back to the same state
+    #[clap(long = "detect-loops")]
+    detect_loops: bool,
+    /// count executed instructions and print a report at the end
+    #[clap(long = "profile")]
+    profile: bool,
+    /// write the I/O of every machine to this file, see the replay command
+    #[clap(long = "record")]
+    record: Option<String>,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+// for commands that load an Intcode program but take no other `IntcodeOpts`
+#[derive(Clap)]
+pub struct PatchOpts {
+    /// change the program as it is loaded, see `intcode::patch` for the format
+    #[clap(long = "patch")]
+    patch: Option<String>,
+}
+
+impl PatchOpts {
+    pub fn apply<T: Cell>(&self, com: &mut IntcodeComputer<T>) {
+        if let Some(path) = &self.patch {
+            let src = fs::read_to_string(path).expect("error reading file");
+            Patch::parse(&src)
+                .unwrap_or_else(|e| panic!("{}", e))
+                .apply(com)
+                .unwrap_or_else(|e| panic!("{}", e));
+        }
+    }
+}
+
+/// what `IntcodeOpts::apply` attached to a machine and its clones
+pub struct Attached<T> {
+    profile: Option<Arc<Mutex<Profile>>>,
+    record: Option<(String, Arc<Mutex<IoLog<T>>>)>,
+}
+
+impl IntcodeOpts {
+    pub fn apply<T: Cell>(&self, com: &mut IntcodeComputer<T>) -> Attached<T> {
+        // patched before recording starts, replays need the same patch
+        self.patch.apply(com);
+        com.set_limits(Limits {
+            max_steps: self.max_steps,
+            timeout: self.timeout.map(Duration::from_secs_f64),
+            detect_loops: self.detect_loops,
+        });
+        let profile = if self.profile {
+            let profile = Arc::new(Mutex::new(Profile::default()));
+            com.enable_profiling(profile.clone());
+            Some(profile)
+        } else {
+            None
+        };
+        let record = self.record.as_ref().map(|path| {
+            let log = Arc::new(Mutex::new(IoLog::default()));
+            com.enable_recording(log.clone());
+            (path.clone(), log)
+        });
+        Attached { profile, record }
+    }
+}
+
+impl<T: Cell> Attached<T> {
+    /// writes the profile to `w` and saves the recording once `com`, the
+    /// machine passed to `apply`, and all of its clones are done
+    pub fn finish<W: Write>(self, com: IntcodeComputer<T>, mut w: W) {
+        // cells too wide for an isize are no valid instruction either
+        let prog = match self.profile {
+            Some(_) => com
+                .memory()
+                .iter()
+                .map(|v| v.to_isize().unwrap_or(isize::MAX))
+                .collect::<Vec<_>>(),
+            None => vec![],
+        };
+        drop(com);
+
+        if let Some(profile) = self.profile {
+            writeln!(w)
+                .and_then(|_| {
+                    profile
+                        .lock()
+                        .expect("profile lock poisoned")
+                        .write_report(&prog, w)
+                })
+                .expect("error writing profile");
+        }
+        if let Some((path, log)) = self.record {
+            let f = fs::File::create(path).expect("error creating file");
+            log.lock()
+                .expect("recording lock poisoned")
+                .write_to(io::BufWriter::new(f))
+                .expect("error writing recording");
+        }
+    }
+}
 
 #[derive(Clap)]
 pub struct Day1 {
@@ -35,12 +152,16 @@ impl Day1 {
 pub struct Day2 {
     #[clap(short = '2', long = "d2", default_value = "inputs/d2")]
     input: String,
+    #[clap(flatten)]
+    intcode: IntcodeOpts,
 }
 
 impl Day2 {
     pub fn run(&self) {
         let f = fs::read_to_string(&self.input).expect("error reading file");
-        let com = day2::parse_input(f).expect("error parsing input");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        com.set_dialect(day2::dialect());
+        let attached = self.intcode.apply(&mut com);
 
         print!(
             "Part 1 Solution: {}\n",
@@ -51,6 +172,8 @@ impl Day2 {
             "Part 2 Solution: {}\n",
             day2::solve_part_2(&mut com.clone()).expect("error solving part 2")
         );
+
+        attached.finish(com, io::stdout());
     }
 }
 
@@ -104,12 +227,15 @@ impl Day4 {
 pub struct Day5 {
     #[clap(short = '5', long = "d5", default_value = "inputs/d5")]
     input: String,
+    #[clap(flatten)]
+    intcode: IntcodeOpts,
 }
 
 impl Day5 {
     pub fn run(&self) {
         let f = fs::read_to_string(&self.input).expect("error reading file");
-        let com = day2::parse_input(f).expect("error parsing input");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        let attached = self.intcode.apply(&mut com);
 
         print!(
             "Part 1 Solution: {}\n",
@@ -120,6 +246,8 @@ impl Day5 {
             "Part 2 Solution: {}\n",
             day5::solve_part_2(&mut com.clone()).expect("error solving part 2")
         );
+
+        attached.finish(com, io::stdout());
     }
 }
 
@@ -147,12 +275,15 @@ impl Day6 {
 pub struct Day7 {
     #[clap(short = '7', long = "d7", default_value = "inputs/d7")]
     input: String,
+    #[clap(flatten)]
+    intcode: IntcodeOpts,
 }
 
 impl Day7 {
     pub fn run(&self) {
         let f = fs::read_to_string(&self.input).expect("error reading file");
-        let com = day2::parse_input(f).expect("error parsing input");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        let attached = self.intcode.apply(&mut com);
 
         print!(
             "Part 1 Solution: {}\n",
@@ -163,6 +294,8 @@ impl Day7 {
             "Part 2 Solution: {}\n",
             day7::solve_part_2(&com).expect("error solving part 2").1
         );
+
+        attached.finish(com, io::stdout());
     }
 }
 
@@ -193,12 +326,15 @@ impl Day8 {
 pub struct Day9 {
     #[clap(short = '9', long = "d9", default_value = "inputs/d9")]
     input: String,
+    #[clap(flatten)]
+    intcode: IntcodeOpts,
 }
 
 impl Day9 {
     pub fn run(&self) {
         let f = fs::read_to_string(&self.input).expect("error reading file");
-        let com = day2::parse_input(f).expect("error parsing input");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        let attached = self.intcode.apply(&mut com);
 
         print!(
             "Part 1 Solution: {}\n",
@@ -209,5 +345,7 @@ impl Day9 {
             "Part 2 Solution: {}\n",
             day9::solve_part_2(&com).expect("error solving part 2")
         );
+
+        attached.finish(com, io::stdout());
     }
 }
@@ -1,8 +1,8 @@
 use intcode::ExecutionError;
 use rayon::prelude::*;
-use std::num::ParseIntError;
+use std::{num::ParseIntError, sync::Mutex};
 
-use crate::intcode::{self, IntcodeComputer};
+use crate::intcode::{self, dialect::Dialect, opcode::Opcode, symbolic, IntcodeComputer, Store};
 
 pub fn parse_input(f: String) -> Result<IntcodeComputer, ParseIntError> {
     let data = f
@@ -14,16 +14,68 @@ pub fn parse_input(f: String) -> Result<IntcodeComputer, ParseIntError> {
     Ok(intcode::new(data))
 }
 
+/// the computer as day 2 describes it, which only adds and multiplies
+pub fn dialect() -> Dialect {
+    Dialect::only(&[Opcode::Add, Opcode::Mul, Opcode::Hlt])
+}
+
 pub fn solve_part_1(com: &mut IntcodeComputer) -> Result<isize, intcode::ExecutionError> {
-    com.set(1, 12);
-    com.set(2, 2);
+    run_with(com, 12, 2)
+}
+
+/// runs the program with `noun` and `verb` patched in, returning address 0
+fn run_with<M: Store<isize>>(
+    com: &mut IntcodeComputer<isize, M>,
+    noun: isize,
+    verb: isize,
+) -> Result<isize, intcode::ExecutionError> {
+    com.set(1, noun);
+    com.set(2, verb);
 
     while !com.step(None)?.0 {}
 
-    Ok(com.get_val(0)?)
+    Ok(com.get_val(0))
+}
+
+const TARGET: isize = 19690720;
+
+/// Solves for the noun and verb directly when address 0 ends up a linear
+/// function of them, which it does for the inputs we have seen, and falls
+/// back to trying every pair otherwise.
+pub fn solve_part_2<M: Store<isize>>(
+    com: &mut IntcodeComputer<isize, M>,
+) -> Result<isize, intcode::ExecutionError> {
+    let (noun, verb) = match invert(com) {
+        Some(found) => found,
+        None => search(com)?,
+    };
+    Ok(noun * 10 + verb)
 }
 
-pub fn solve_part_2(com: &mut IntcodeComputer) -> Result<isize, intcode::ExecutionError> {
+/// steps the symbolic run may take when the machine has no step budget or
+/// timeout of its own
+const SYMBOLIC_STEPS: u64 = 1_000_000;
+
+/// The noun and verb solved for symbolically, checked on the machine
+/// itself. The symbolic run gets the machine's dialect and limits, and
+/// `SYMBOLIC_STEPS` if those do not bound it, as it cannot tell a loop.
+fn invert<M: Store<isize>>(com: &IntcodeComputer<isize, M>) -> Option<(isize, isize)> {
+    let mut limits = com.limits();
+    if limits.max_steps.is_none() && limits.timeout.is_none() {
+        limits.max_steps = Some(SYMBOLIC_STEPS);
+    }
+    let out = symbolic::run(&com.segments(), &[1, 2], com.dialect(), &limits).ok()?;
+    let found = out.cell(0).linear()?.solve(&[1, 2], 0..100, TARGET)?;
+    let (noun, verb) = (found[0], found[1]);
+    match run_with(&mut com.clone(), noun, verb) {
+        Ok(TARGET) => Some((noun, verb)),
+        _ => None,
+    }
+}
+
+fn search<M: Store<isize>>(
+    com: &IntcodeComputer<isize, M>,
+) -> Result<(isize, isize), intcode::ExecutionError> {
     let mut inputs: Vec<(isize, isize)> = vec![];
     for noun in 0..100 {
         for verb in 0..100 {
@@ -31,33 +83,107 @@ pub fn solve_part_2(com: &mut IntcodeComputer) -> Result<isize, intcode::Executi
         }
     }
 
+    // how many pairs ran into a limit, and the last one
+    let cut_off = Mutex::new((0, None));
     let found = inputs
         .par_iter()
-        .find_any(|(n, v)| {
-            let mut c = com.clone();
-            c.set(1, *n);
-            c.set(2, *v);
-            while !c.step(None).expect("error stepping in intcode com").0 {}
-            c.get_val(0)
-                .expect("error getting pos 0, this should not happen")
-                == 19690720
-        })
-        .ok_or_else(|| ExecutionError {
-            msg: format!("requested value not found"),
-            missing_input: false,
-        })?;
-
-    Ok(found.0 * 10 + found.1)
+        .find_map_any(|(n, v)| match run_with(&mut com.clone(), *n, *v) {
+            Ok(TARGET) => Some(Ok((*n, *v))),
+            Ok(_) => None,
+            // a pair that makes the program run away is not the one we are after
+            Err(ExecutionError::LimitExceeded(l)) => {
+                let mut c = cut_off.lock().expect("lock poisoned");
+                *c = (c.0 + 1, Some(l));
+                None
+            }
+            Err(e) => Some(Err(e)),
+        });
+    match found {
+        Some(found) => found,
+        // with every pair cut off, the limits are to blame rather than the program
+        None => match cut_off.into_inner().expect("lock poisoned") {
+            (n, Some(l)) if n == inputs.len() => Err(ExecutionError::LimitExceeded(l)),
+            _ => Err(ExecutionError::NoSolution),
+        },
+    }
 }
 
 #[cfg(test)]
 mod tests {
     static TEST_INPUT: &'static str = "1,9,10,3,2,3,11,0,99,30,40,50";
-    use super::parse_input;
+    use super::*;
 
     #[test]
     fn parses_fine() {
         let res = parse_input(String::from(TEST_INPUT));
         assert!(res.is_ok());
     }
+
+    #[test]
+    fn search_propagates_errors() {
+        // every pair runs into opcode 98
+        let com = parse_input(String::from("1,0,0,0,98")).unwrap();
+        assert_eq!(
+            search(&com),
+            Err(ExecutionError::UnknownOpcode {
+                addr: 4,
+                instruction: 98
+            })
+        );
+    }
+
+    #[test]
+    fn search_reports_limits() {
+        use crate::intcode::limits::{Limit, Limits};
+
+        // every pair runs forever
+        let mut com = parse_input(String::from("1,0,0,0,1105,1,4")).unwrap();
+        com.set_limits(Limits {
+            max_steps: Some(10),
+            ..Limits::default()
+        });
+        assert_eq!(
+            search(&com),
+            Err(ExecutionError::LimitExceeded(Limit::Steps(10)))
+        );
+    }
+
+    #[test]
+    fn invert_stays_within_limits() {
+        use crate::intcode::limits::Limits;
+
+        // the noun doubles 45 times, so address 0 is 2^45 * noun + verb
+        let mut src = String::from("1,0,0,3");
+        (0..45).for_each(|_| src.push_str(",1,1,1,1"));
+        src.push_str(",1,1,2,0,99");
+        let mut com = parse_input(src).unwrap();
+        com.set_dialect(dialect());
+        assert_eq!(invert(&com), None);
+        assert_eq!(solve_part_2(&mut com), Err(ExecutionError::NoSolution));
+
+        // 121 doublings make the coefficient too big to solve with
+        let mut src = String::from("1,0,0,3");
+        (0..121).for_each(|_| src.push_str(",1,1,1,1"));
+        src.push_str(",1,1,2,0,99");
+        let mut com = parse_input(src).unwrap();
+        com.set_dialect(dialect());
+        assert_eq!(invert(&com), None);
+        assert_eq!(solve_part_2(&mut com), Err(ExecutionError::NoSolution));
+
+        // a write far out
+        let mut com = parse_input(String::from("1,0,0,100000000000000,99")).unwrap();
+        com.set_dialect(dialect());
+        assert_eq!(solve_part_2(&mut com), Err(ExecutionError::NoSolution));
+
+        // address 0 ends up 100 * noun + verb + 19689486, but not within 2 steps
+        let src = "1,0,0,3,2,1,17,1,1,1,2,0,1,0,18,0,99,100,19689486";
+        let mut com = parse_input(String::from(src)).unwrap();
+        com.set_dialect(dialect());
+        assert_eq!(invert(&com), Some((12, 34)));
+        com.set_limits(Limits {
+            max_steps: Some(2),
+            ..Limits::default()
+        });
+        assert_eq!(invert(&com), None);
+    }
 }
@@ -1,37 +1,27 @@
-use crate::intcode::{self, IntcodeComputer};
+use std::collections::VecDeque;
 
-pub fn solve_part_1(com: &mut IntcodeComputer) -> Result<isize, intcode::ExecutionError> {
-    com.step(Some(1))?;
-    loop {
-        let res = com.step(None)?;
-        if res.1.is_some() {
-            let val = res.1.unwrap();
-            if val != 0 {
-                return Ok(val);
-            }
-        }
-        if res.0 {
-            break;
-        }
-    }
+use crate::intcode::{self, IntcodeComputer, StopEvent};
 
-    Ok(0)
+pub fn solve_part_1(com: &mut IntcodeComputer) -> Result<isize, intcode::ExecutionError> {
+    run_diagnostics(com, 1)
 }
 
 pub fn solve_part_2(com: &mut IntcodeComputer) -> Result<isize, intcode::ExecutionError> {
-    com.step(Some(5))?;
-    loop {
-        let res = com.step(None)?;
-        if res.1.is_some() {
-            let val = res.1.unwrap();
-            if val != 0 {
-                return Ok(val);
-            }
-        }
-        if res.0 {
-            break;
-        }
+    run_diagnostics(com, 5)
+}
+
+/// runs the test program for `system` and returns the first non zero
+/// output, which is the diagnostic code if every test passed
+fn run_diagnostics(
+    com: &mut IntcodeComputer,
+    system: isize,
+) -> Result<isize, intcode::ExecutionError> {
+    let mut outputs = vec![];
+    match com.run(&mut VecDeque::from(vec![system]), &mut outputs)? {
+        StopEvent::Finished => {}
+        StopEvent::LimitExceeded(l) => return Err(intcode::ExecutionError::LimitExceeded(l)),
+        _ => return Err(intcode::ExecutionError::UnexpectedInput),
     }
 
-    Ok(0)
+    Ok(outputs.into_iter().find(|v| *v != 0).unwrap_or(0))
 }
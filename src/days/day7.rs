@@ -1,118 +1,87 @@
-use crate::intcode::{self, IntcodeComputer};
-use intcode::ExecutionError;
+use crate::intcode::{
+    executor::{channel, Executor},
+    ExecutionError, IntcodeComputer, StopEvent,
+};
 use permutohedron::heap_recursive;
 use rayon::prelude::*;
 
 pub fn solve_part_1(com: &IntcodeComputer) -> Result<(Vec<isize>, isize), ExecutionError> {
-    let mut phases: Vec<isize> = (0..5).collect();
-    let mut permutations = Vec::new();
-    heap_recursive(&mut phases, |p| permutations.push(p.to_vec()));
-
-    let runs = permutations
-        .into_par_iter()
-        .map(|perm| -> Result<(Vec<isize>, isize), ExecutionError> {
-            let mut val = 0;
-            perm.iter()
-                .try_for_each(|phase| -> Result<(), ExecutionError> {
-                    let mut loc_com = com.clone();
-                    this_should_not_end_or_output_shit(loc_com.step_pause_on_io())?;
-                    this_should_not_end_or_output_shit(loc_com.step(Some(*phase)))?;
-                    this_should_not_end_or_output_shit(loc_com.step_pause_on_io())?;
-                    this_should_not_end_or_output_shit(loc_com.step(Some(val)))?;
-                    val = loc_com.should_output()?;
-                    Ok(())
-                })?;
-            Ok((perm, val))
-        })
-        .collect::<Result<Vec<(Vec<_>, _)>, _>>()?;
-
-    let max = runs
-        .into_par_iter()
-        .max_by_key(|run| run.1)
-        .ok_or(ExecutionError {
-            msg: "could not find max, is none".into(),
-            missing_input: false,
-        })?;
-
-    Ok(max)
+    max_signal(com, 0..5, chain)
 }
 
 pub fn solve_part_2(com: &IntcodeComputer) -> Result<(Vec<isize>, isize), ExecutionError> {
-    let mut phases: Vec<isize> = (5..10).collect();
+    max_signal(com, 5..10, feedback)
+}
+
+fn max_signal(
+    com: &IntcodeComputer,
+    phases: std::ops::Range<isize>,
+    signal: fn(&IntcodeComputer, &[isize]) -> Result<isize, ExecutionError>,
+) -> Result<(Vec<isize>, isize), ExecutionError> {
+    let mut phases: Vec<isize> = phases.collect();
     let mut permutations = Vec::new();
     heap_recursive(&mut phases, |p| permutations.push(p.to_vec()));
 
-    let runs = permutations
+    permutations
         .into_par_iter()
-        .map(|perm| -> Result<(Vec<isize>, isize), ExecutionError> {
-            let mut val = 0;
-            let mut output_val = 0;
-
-            // here we prime the amplifiers
-            let mut amplifiers: Vec<_> = perm
-                .par_iter()
-                .map(|p| -> Result<IntcodeComputer, ExecutionError> {
-                    let mut loc_com = com.clone();
-                    // find first input node
-                    this_should_not_end_or_output_shit(loc_com.step_pause_on_io())?;
-                    // give phase setting
-                    this_should_not_end_or_output_shit(loc_com.step(Some(*p)))?;
-                    // prime it to the next input
+        .map(|perm| signal(com, &perm).map(|s| (perm, s)))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .max_by_key(|run| run.1)
+        .ok_or(ExecutionError::NoSolution)
+}
 
-                    Ok(loc_com)
-                })
-                .collect::<Result<_, _>>()?;
+/// each amplifier reads its phase setting, then the signal out of the one
+/// before it, the first one gets 0
+pub(crate) fn chain(com: &IntcodeComputer, phases: &[isize]) -> Result<isize, ExecutionError> {
+    phases.iter().try_fold(0, |signal, phase| {
+        let mut amp = com.clone().into_coroutine();
+        amp.respond(*phase);
+        amp.call(signal)?.ok_or(ExecutionError::UnexpectedHalt)
+    })
+}
 
-            let mut _next = -1;
-            let mut get_next = || -> isize {
-                _next = _next + 1;
-                _next = _next % 5;
-                _next
-            };
+/// like `chain`, but the last amplifier feeds the first one until they halt
+pub(crate) fn feedback(com: &IntcodeComputer, phases: &[isize]) -> Result<isize, ExecutionError> {
+    // amplifier i reads from channel i and sends to the next one around
+    let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| channel()).unzip();
+    for (tx, phase) in senders.iter().zip(phases) {
+        tx.send(*phase);
+    }
+    senders[0].send(0);
 
-            loop {
-                let amp = get_next();
-                // should be expecting an input
-                if amplifiers[amp as usize].should_stop_on_input()? {
-                    return Ok((perm, output_val));
-                }
-                this_should_not_end_or_output_shit(amplifiers[amp as usize].step(Some(val)))?;
-                let next_action = amplifiers[amp as usize].step_pause_on_io()?;
-                if next_action.0 {
-                    return Ok((perm, output_val));
-                }
-                val = next_action.1.ok_or_else(|| -> ExecutionError {
-                    "not expecting it to ask for input right after".into()
-                })?;
-                if amp == 4 {
-                    output_val = val;
-                }
-            }
+    let mut exec = Executor::new();
+    let amps = receivers
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut rx)| {
+            let mut amp = com.clone();
+            let tx = senders[(i + 1) % senders.len()].clone();
+            exec.spawn(async move {
+                let mut last = None;
+                let mut out = |v| {
+                    last = Some(v);
+                    tx.send(v);
+                };
+                let event = amp.run_async(&mut rx, &mut out).await?;
+                Ok((event, last))
+            })
         })
-        .collect::<Result<Vec<(Vec<_>, _)>, _>>()?;
+        .collect::<Vec<_>>();
+    drop(senders);
 
-    let max = runs
-        .into_par_iter()
-        .max_by_key(|run| run.1)
-        .ok_or(ExecutionError {
-            msg: "could not find max, is none".into(),
-            missing_input: false,
-        })?;
-
-    Ok(max)
-}
-
-fn this_should_not_end_or_output_shit(
-    r: Result<(bool, Option<isize>), ExecutionError>,
-) -> Result<(), ExecutionError> {
-    let r = r?;
-    if r.0 {
-        return Err("this should not end".into());
-    };
-    if r.1.is_some() {
-        return Err("this should not output anything".into());
-    };
-    Ok(())
+    if exec.run() > 0 {
+        return Err(ExecutionError::UnexpectedInput);
+    }
+    // the last signal out of the last amplifier is the answer
+    let mut signal = None;
+    for amp in amps {
+        match amp.take().expect("finished tasks have a result")? {
+            (StopEvent::Finished, last) => signal = last,
+            _ => return Err(ExecutionError::UnexpectedInput),
+        }
+    }
+    signal.ok_or(ExecutionError::UnexpectedHalt)
 }
 
 #[cfg(test)]
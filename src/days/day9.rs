@@ -1,59 +1,35 @@
-use crate::intcode::{ExecutionError, IntcodeComputer, StopEvent};
+use std::collections::VecDeque;
 
-pub fn solve_part_1(com: &IntcodeComputer) -> Result<isize, ExecutionError> {
-    let mut loc_com = com.clone();
-    if loc_com.should_stop_on_input()? {
-        return Err("expecting input".into());
-    };
+use crate::intcode::{ExecutionError, IntcodeComputer, Store};
 
-    loc_com.step(Some(1))?;
-
-    let (outputs, stop_mode) = loc_com.accumulate_output_until_action()?;
-
-    if stop_mode != StopEvent::Finished {
-        return Err("expecting to output values until finishing".into());
-    };
-
-    if outputs.len() != 1 {
-        println!(
-            "outputs:\n {}",
-            outputs
-                .iter()
-                .map(|t| format!("{}", t))
-                .collect::<Vec<_>>()
-                .join("\n")
-        );
-        return Err(format!("expecting one single output, got {}", outputs.len()).into());
-    };
-
-    Ok(outputs[0])
+pub fn solve_part_1<M: Store<isize>>(
+    com: &IntcodeComputer<isize, M>,
+) -> Result<isize, ExecutionError> {
+    boost(com, 1)
 }
 
-pub fn solve_part_2(com: &IntcodeComputer) -> Result<isize, ExecutionError> {
-    let mut loc_com = com.clone();
-    if loc_com.should_stop_on_input()? {
-        return Err("expecting input".into());
-    };
-
-    loc_com.step(Some(2))?;
-
-    let (outputs, stop_mode) = loc_com.accumulate_output_until_action()?;
-
-    if stop_mode != StopEvent::Finished {
-        return Err("expecting to output values until finishing".into());
-    };
-
-    if outputs.len() != 1 {
-        println!(
-            "outputs:\n {}",
-            outputs
-                .iter()
-                .map(|t| format!("{}", t))
-                .collect::<Vec<_>>()
-                .join("\n")
-        );
-        return Err(format!("expecting one single output, got {}", outputs.len()).into());
-    };
+pub fn solve_part_2<M: Store<isize>>(
+    com: &IntcodeComputer<isize, M>,
+) -> Result<isize, ExecutionError> {
+    boost(com, 2)
+}
 
-    Ok(outputs[0])
+/// runs BOOST in `mode`, which should output nothing but the keycode or
+/// the coordinates
+fn boost<M: Store<isize>>(
+    com: &IntcodeComputer<isize, M>,
+    mode: isize,
+) -> Result<isize, ExecutionError> {
+    let outputs = com
+        .clone()
+        .outputs(VecDeque::from(vec![mode]))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match outputs[..] {
+        [v] => Ok(v),
+        _ => Err(ExecutionError::UnexpectedOutputCount {
+            expected: 1,
+            outputs,
+        }),
+    }
 }
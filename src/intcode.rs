@@ -1,290 +1,745 @@
-use std::{collections::HashMap, error::Error};
+use std::{
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex},
+};
 
-use modes::OpMode;
+use self::io::{Input, Output};
+use cache::OpCache;
+pub use cell::Cell;
+use coroutine::{Coroutine, Outputs, Yield};
+use dialect::{Dialect, Effect, Exec};
+use executor::Receiver;
+use history::{History, Rewound};
+use limits::{Guard, Limit, Limits};
+use memory::Memory;
+pub use memory::{HashMemory, Store};
+use modes::{DecodeError, Decoded, OpMode};
+use opcode::Opcode;
+use profile::{Profile, Profiler};
+use replay::{Io, IoLog, Recorder};
+use trace::{Access, Record, Tracer};
 
+pub mod ascii;
+pub mod asm;
+mod cache;
+mod cell;
+pub mod cfg;
+#[cfg(test)]
+mod conformance;
+pub mod coroutine;
+pub mod debugger;
+pub mod dialect;
+pub mod disasm;
+pub mod executor;
+pub mod fuzz;
+pub mod history;
+pub mod io;
+pub mod limits;
+mod memory;
 mod modes;
+pub mod network;
+pub mod opcode;
+pub mod patch;
+pub mod profile;
+pub mod reference;
+pub mod replay;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
 
 #[derive(PartialEq, Debug)]
-pub enum StopEvent {
+pub enum StopEvent<T = isize> {
     Finished,
     WaitingOnInput,
+    Watchpoint(Record<T>),
+    LimitExceeded(Limit),
 }
 
+/// An Intcode machine whose memory cells hold `T`, see `Cell`.
 #[derive(Clone)]
-pub struct IntcodeComputer {
-    mem: HashMap<usize, isize>,
+pub struct IntcodeComputer<T = isize, M = Memory<T>> {
+    mem: M,
     pos: usize,
     relative_base: isize,
+    /// report overflowing `add` and `mul` as errors instead of wrapping
+    checked: bool,
+    tracer: Option<Box<Tracer<T>>>,
+    history: Option<Box<History<T, M>>>,
+    guard: Option<Box<Guard<T, M>>>,
+    profiler: Option<Box<Profiler>>,
+    recorder: Option<Box<Recorder<T>>>,
+    /// the instructions the machine runs, shared between clones
+    dialect: Arc<Dialect<T, M>>,
+    /// pre-decoded instructions, `None` to decode every step from scratch
+    cache: Option<OpCache>,
 }
 
 pub fn new(d: Vec<isize>) -> IntcodeComputer {
-    let mut map: HashMap<usize, isize> = HashMap::new();
-    d.iter().enumerate().for_each(|(i, v)| {
-        map.insert(i, *v);
-    });
+    from_cells(d)
+}
 
+/// a machine with a cell type other than `isize`
+pub fn from_cells<T: Cell>(d: Vec<T>) -> IntcodeComputer<T> {
+    let mem = Memory::from(d);
+    let dialect = Dialect::standard();
     IntcodeComputer {
-        mem: map,
+        cache: Some(OpCache::translate(&mem, &dialect)),
+        dialect: Arc::new(dialect),
+        mem,
         pos: 0,
         relative_base: 0,
+        checked: false,
+        tracer: None,
+        history: None,
+        guard: None,
+        profiler: None,
+        recorder: None,
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct PositionNotFoundError {
-    pos: usize,
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionError {
+    UnknownOpcode {
+        addr: usize,
+        instruction: isize,
+    },
+    InvalidMode {
+        addr: usize,
+        instruction: isize,
+    },
+    WriteInImmediateMode {
+        addr: usize,
+        instruction: isize,
+    },
+    NegativeAddress {
+        addr: usize,
+        instruction: isize,
+        target: isize,
+    },
+    MissingInput {
+        addr: usize,
+    },
+    /// in checked mode, a result did not fit in the cell type; in any mode,
+    /// an address or relative base offset did not fit in an `isize`
+    Overflow {
+        addr: usize,
+        instruction: isize,
+    },
+    /// the machine ran into one of the limits set with `set_limits`
+    LimitExceeded(Limit),
+    /// the machine halted where the caller expected it to keep running
+    UnexpectedHalt,
+    /// the machine produced a value where the caller expected none
+    UnexpectedOutput(isize),
+    /// the machine asked for input where the caller expected output or a halt
+    UnexpectedInput,
+    /// the machine finished with a different number of outputs than expected
+    UnexpectedOutputCount {
+        expected: usize,
+        outputs: Vec<isize>,
+    },
+    NoSolution,
 }
 
-#[derive(Debug, Clone)]
-pub struct ExecutionError {
-    pub msg: String,
-    pub missing_input: bool,
-}
-
-impl From<PositionNotFoundError> for ExecutionError {
-    fn from(e: PositionNotFoundError) -> ExecutionError {
-        ExecutionError {
-            msg: format!("current pointer at {}, not found in memory", e.pos),
-            missing_input: false,
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::UnknownOpcode { addr, instruction } => {
+                write!(f, "unknown opcode {} at {}", instruction, addr)
+            }
+            ExecutionError::InvalidMode { addr, instruction } => write!(
+                f,
+                "invalid parameter mode in instruction {} at {}",
+                instruction, addr
+            ),
+            ExecutionError::WriteInImmediateMode { addr, instruction } => write!(
+                f,
+                "write parameter in immediate mode in instruction {} at {}",
+                instruction, addr
+            ),
+            ExecutionError::NegativeAddress {
+                addr,
+                instruction,
+                target,
+            } => write!(
+                f,
+                "instruction {} at {} accesses negative address {}",
+                instruction, addr, target
+            ),
+            ExecutionError::MissingInput { addr } => {
+                write!(f, "missing input for instruction at {}", addr)
+            }
+            ExecutionError::Overflow { addr, instruction } => {
+                write!(f, "overflow in instruction {} at {}", instruction, addr)
+            }
+            ExecutionError::LimitExceeded(l) => write!(f, "{}", l),
+            ExecutionError::UnexpectedHalt => write!(f, "machine halted unexpectedly"),
+            ExecutionError::UnexpectedOutput(v) => write!(f, "unexpected output {}", v),
+            ExecutionError::UnexpectedInput => write!(f, "machine unexpectedly asked for input"),
+            ExecutionError::UnexpectedOutputCount { expected, outputs } => write!(
+                f,
+                "expecting {} output(s), got {}: {:?}",
+                expected,
+                outputs.len(),
+                outputs
+            ),
+            ExecutionError::NoSolution => write!(f, "no solution found"),
         }
     }
 }
 
-impl From<Box<dyn Error>> for ExecutionError {
-    fn from(e: Box<dyn Error>) -> ExecutionError {
-        ExecutionError {
-            msg: e.to_string(),
-            missing_input: false,
-        }
-    }
-}
+impl Error for ExecutionError {}
 
-impl From<String> for ExecutionError {
-    fn from(e: String) -> ExecutionError {
-        ExecutionError {
-            msg: e,
-            missing_input: false,
+impl<T: Cell, M: Store<T>> IntcodeComputer<T, M> {
+    pub fn step(&mut self, input: Option<T>) -> Result<(bool, Option<T>), ExecutionError> {
+        if self.history.is_none()
+            && self.guard.is_none()
+            && self.profiler.is_none()
+            && self.recorder.is_none()
+        {
+            return self.execute(input);
         }
+        self.step_watched(input)
     }
-}
 
-impl From<&str> for ExecutionError {
-    fn from(s: &str) -> ExecutionError {
-        ExecutionError {
-            msg: s.to_string(),
-            missing_input: false,
+    /// `step` with the bookkeeping for history, limits, profiling and recording
+    fn step_watched(&mut self, input: Option<T>) -> Result<(bool, Option<T>), ExecutionError> {
+        if let Some(g) = self.guard.as_ref() {
+            g.check().map_err(ExecutionError::LimitExceeded)?;
         }
-    }
-}
+        if let Some(h) = self.history.as_mut() {
+            h.begin(&self.mem, self.pos, self.relative_base);
+        }
+        let (at, code) = (self.pos, self.instruction() % 100);
+        let consumed = if self.wants_input() {
+            input.clone()
+        } else {
+            None
+        };
 
-impl IntcodeComputer {
-    pub fn step(&mut self, input: Option<isize>) -> Result<(bool, Option<isize>), ExecutionError> {
-        let opc = self
-            .mem
-            .get(&self.pos)
-            .ok_or(PositionNotFoundError { pos: self.pos })?;
-
-        match opc % 100 {
-            1 => {
-                let mode = modes::parse_op_mode(opc / 100, 3)?;
-                let v1 = self.get_param(1, mode[0])?;
-                let v2 = self.get_param(2, mode[1])?;
-                let v3 = self.get_param_write(3, mode[2])?;
-                self.set(v3, v1 + v2);
-                self.pos += 4;
-                Ok((false, None))
+        let r = self.execute(input);
+        if let (Some(p), Ok(_)) = (self.profiler.as_mut(), &r) {
+            let p = p.local();
+            let op = Opcode::from_code(code);
+            p.count(at, op);
+            if let Some(op @ (Opcode::Jnz | Opcode::Jz)) = op {
+                // a jump to the next instruction counts as not taken
+                p.jump(at, self.pos != at + op.size());
             }
-            2 => {
-                let mode = modes::parse_op_mode(opc / 100, 3)?;
-                let v1 = self.get_param(1, mode[0])?;
-                let v2 = self.get_param(2, mode[1])?;
-                let v3 = self.get_param_write(3, mode[2])?;
-                self.set(v3, v1 * v2);
-                self.pos += 4;
-                Ok((false, None))
+        }
+        let stepped = matches!(r, Ok((false, _)));
+        if let Some(rec) = self.recorder.as_mut().filter(|_| stepped) {
+            if let Some(v) = consumed.clone() {
+                rec.record(Io::In(v));
             }
-            3 => {
-                let mode = modes::parse_op_mode(opc / 100, 1)?;
-                let v1 = self.get_param_write(1, mode[0])?;
-                let input = input.ok_or_else(|| ExecutionError {
-                    msg: "missing input".to_string(),
-                    missing_input: true,
-                })?;
-                self.set(v1, input);
-                self.pos += 2;
-                Ok((false, None))
+            if let Ok((_, Some(v))) = &r {
+                rec.record(Io::Out(v.clone()));
             }
-            4 => {
-                let mode = modes::parse_op_mode(opc / 100, 1)?;
-                let v1 = self.get_param(1, mode[0])?;
-                self.pos += 2;
-                Ok((false, Some(v1)))
+            rec.stepped();
+        }
+        if let Some(g) = self.guard.as_mut().filter(|_| stepped) {
+            g.stepped(&self.mem, self.pos, self.relative_base, consumed.is_some());
+        }
+        if let Some(h) = self.history.as_mut() {
+            if stepped {
+                h.commit(consumed);
+            } else {
+                h.discard();
             }
-            5 => {
-                let mode = modes::parse_op_mode(opc / 100, 2)?;
-                let v1 = self.get_param(1, mode[0])?;
-                let v2 = self.get_param(2, mode[1])?;
-                if v1 != 0 {
-                    self.pos = v2 as usize;
-                } else {
-                    self.pos += 3;
+        }
+        r
+    }
+
+    fn execute(&mut self, input: Option<T>) -> Result<(bool, Option<T>), ExecutionError> {
+        let d = match self.cache.as_ref().and_then(|c| c.get(self.pos)) {
+            Some(d) => d,
+            None => {
+                let d = self.decode()?;
+                if let Some(c) = self.cache.as_mut() {
+                    c.insert(self.pos, d);
                 }
-                Ok((false, None))
+                d
             }
-            6 => {
-                let mode = modes::parse_op_mode(opc / 100, 2)?;
-                let v1 = self.get_param(1, mode[0])?;
-                let v2 = self.get_param(2, mode[1])?;
-                if v1 == 0 {
-                    self.pos = v2 as usize;
-                } else {
-                    self.pos += 3;
-                }
-                Ok((false, None))
+        };
+
+        let exec = match self.dialect.get(d.code()) {
+            Some(op) => op.exec,
+            None => {
+                return Err(ExecutionError::UnknownOpcode {
+                    addr: self.pos,
+                    instruction: self.instruction(),
+                })
             }
-            7 => {
-                let mode = modes::parse_op_mode(opc / 100, 3)?;
-                let v1 = self.get_param(1, mode[0])?;
-                let v2 = self.get_param(2, mode[1])?;
-                let v3 = self.get_param_write(3, mode[2])?;
-                self.set(v3, if v1 < v2 { 1 } else { 0 });
-                self.pos += 4;
-                Ok((false, None))
+        };
+        let mut x = Exec {
+            com: self,
+            d,
+            input,
+            jump: None,
+        };
+        let effect = exec(&mut x)?;
+        let jump = x.jump;
+        let output = match effect {
+            Effect::Next => None,
+            Effect::Output(v) => Some(v),
+            Effect::Halt => return Ok((true, None)),
+        };
+        self.pos = jump.unwrap_or(self.pos + d.size());
+        Ok((false, output))
+    }
+
+    /// splits the cell under the instruction pointer, the slow way
+    fn decode(&self) -> Result<Decoded, ExecutionError> {
+        let (addr, instruction) = (self.pos, self.instruction());
+        self.dialect.decode(instruction).map_err(|e| match e {
+            DecodeError::UnknownOpcode => ExecutionError::UnknownOpcode { addr, instruction },
+            DecodeError::InvalidMode(_) => ExecutionError::InvalidMode { addr, instruction },
+            DecodeError::WriteInImmediateMode => {
+                ExecutionError::WriteInImmediateMode { addr, instruction }
             }
-            8 => {
-                let mode = modes::parse_op_mode(opc / 100, 3)?;
-                let v1 = self.get_param(1, mode[0])?;
-                let v2 = self.get_param(2, mode[1])?;
-                let v3 = self.get_param_write(3, mode[2])?;
-                self.set(v3, if v1 == v2 { 1 } else { 0 });
-                self.pos += 4;
-                Ok((false, None))
+        })
+    }
+
+    /// Runs until the machine halts or `input` has no value for an `in`
+    /// instruction, sending every output to `output` as it is produced.
+    pub fn run<I: Input<T>, O: Output<T>>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<StopEvent<T>, ExecutionError> {
+        loop {
+            if let Some(hit) = self.take_watch_hit() {
+                return Ok(StopEvent::Watchpoint(hit));
             }
-            9 => {
-                let mode = modes::parse_op_mode(opc / 100, 1)?;
-                let v1 = self.get_param(1, mode[0])?;
-                self.relative_base += v1;
-                self.pos += 2;
-                Ok((false, None))
+            let val = if self.wants_input() {
+                match input.read() {
+                    Some(v) => Some(v),
+                    None => return Ok(StopEvent::WaitingOnInput),
+                }
+            } else {
+                None
+            };
+            if let Some(event) = self.run_step(val, output)? {
+                return Ok(event);
             }
-            99 => Ok((true, None)),
-            _ => Err(ExecutionError {
-                msg: format!("unknown opcode {}", opc),
-                missing_input: false,
-            }),
         }
     }
 
-    pub fn step_pause_on_io(&mut self) -> Result<(bool, Option<isize>), ExecutionError> {
+    /// `run` for machines on an `Executor`: reading input waits until a value
+    /// is sent, and only stops the machine once every sender is gone.
+    pub async fn run_async<O: Output<T>>(
+        &mut self,
+        input: &mut Receiver<T>,
+        output: &mut O,
+    ) -> Result<StopEvent<T>, ExecutionError> {
         loop {
-            match self.step(None) {
-                Ok(opt) => {
-                    if opt.0 == true {
-                        return Ok((true, None));
-                    }
-                    if opt.1.is_some() {
-                        return Ok((false, opt.1));
-                    }
-                }
-                Err(e) => {
-                    if e.missing_input {
-                        return Ok((false, None));
-                    }
+            if let Some(hit) = self.take_watch_hit() {
+                return Ok(StopEvent::Watchpoint(hit));
+            }
+            let val = if self.wants_input() {
+                match input.recv().await {
+                    Some(v) => Some(v),
+                    None => return Ok(StopEvent::WaitingOnInput),
                 }
+            } else {
+                None
+            };
+            if let Some(event) = self.run_step(val, output)? {
+                return Ok(event);
             }
         }
     }
 
-    pub fn should_output(&mut self) -> Result<isize, ExecutionError> {
-        let r = self.step_pause_on_io()?;
-        if (r).0 {
-            return Err("this should not end".into());
-        };
-        (r.1).ok_or("expecting value".into())
+    /// One step of `run` and `run_async`, fed `val` if the machine reads
+    /// input. Returns the event stopping the run, if any, so both handle
+    /// halts, outputs and limits the same way.
+    fn run_step<O: Output<T>>(
+        &mut self,
+        val: Option<T>,
+        output: &mut O,
+    ) -> Result<Option<StopEvent<T>>, ExecutionError> {
+        match self.step(val) {
+            Ok((true, _)) => Ok(Some(StopEvent::Finished)),
+            Ok((false, Some(v))) => {
+                output.write(v);
+                Ok(None)
+            }
+            Ok((false, None)) => Ok(None),
+            Err(ExecutionError::LimitExceeded(l)) => Ok(Some(StopEvent::LimitExceeded(l))),
+            Err(e) => Err(e),
+        }
     }
 
-    pub fn should_stop_on_input(&mut self) -> Result<bool, ExecutionError> {
-        let r = self.step_pause_on_io()?;
-        if (r.1).is_some() {
-            Err("not expecting output".into())
-        } else {
-            Ok(r.0)
+    /// makes `add` and `mul` fail with `ExecutionError::Overflow` instead of
+    /// wrapping around when the result does not fit in a cell
+    pub fn check_overflow(&mut self, on: bool) {
+        self.checked = on;
+    }
+
+    /// the outputs of the machine as an iterator, see `Outputs`
+    pub fn outputs<I: Input<T>>(self, input: I) -> Outputs<T, I, M> {
+        Outputs::new(self, input)
+    }
+
+    /// runs the machine in steps driven by the caller, see `Coroutine`
+    pub fn into_coroutine(self) -> Coroutine<T, Self, M> {
+        Coroutine::new(self)
+    }
+
+    /// Makes the machine run the instructions of `dialect` from now on,
+    /// instead of the standard ones.
+    pub fn set_dialect(&mut self, dialect: Dialect<T, M>) {
+        self.dialect = Arc::new(dialect);
+        if self.cache.is_some() {
+            self.cache = Some(OpCache::translate(&self.mem, &self.dialect));
         }
     }
 
-    pub fn accumulate_output_until_action(
-        &mut self,
-    ) -> Result<(Vec<isize>, StopEvent), ExecutionError> {
-        let mut outputs = Vec::new();
-        loop {
-            let r = self.step_pause_on_io()?;
-            if r.0 {
-                return Ok((outputs, StopEvent::Finished));
-            }
-            match r.1 {
-                Some(v) => outputs.push(v),
-                None => {
-                    return Ok((outputs, StopEvent::WaitingOnInput));
-                }
-            }
+    /// the instructions the machine understands
+    pub fn dialect(&self) -> &Dialect<T, M> {
+        &self.dialect
+    }
+
+    /// whether the instruction under the instruction pointer takes input
+    pub fn wants_input(&self) -> bool {
+        self.dialect
+            .get(self.instruction() % 100)
+            .is_some_and(|op| op.input)
+    }
+
+    /// Turns the table of pre-decoded instructions on or off, see `OpCache`.
+    /// It is on by default; off, every step decodes its opcode cell anew.
+    pub fn use_op_cache(&mut self, on: bool) {
+        if !on {
+            self.cache = None;
+        } else if self.cache.is_none() {
+            self.cache = Some(OpCache::translate(&self.mem, &self.dialect));
+        }
+    }
+
+    /// A machine with the same memory and registers keeping its cells in
+    /// `N`, see `HashMemory`. It runs the standard dialect and leaves
+    /// tracing, limits, profiling, recording and history behind.
+    pub fn with_store<N: Store<T>>(&self) -> IntcodeComputer<T, N> {
+        let mem = N::from_segments(&self.mem.segments(), self.mem.extent());
+        let dialect = Dialect::standard();
+        IntcodeComputer {
+            cache: self
+                .cache
+                .as_ref()
+                .map(|_| OpCache::translate(&mem, &dialect)),
+            dialect: Arc::new(dialect),
+            mem,
+            pos: self.pos,
+            relative_base: self.relative_base,
+            checked: self.checked,
+            tracer: None,
+            history: None,
+            guard: None,
+            profiler: None,
+            recorder: None,
         }
     }
 
-    fn get_param(&mut self, shift: usize, mode: OpMode) -> Result<isize, PositionNotFoundError> {
+    fn get_param(&mut self, shift: usize, mode: OpMode) -> Result<T, ExecutionError> {
+        let raw = self.mem.get(self.pos + shift);
         match mode {
             OpMode::Position => {
-                let pos = self.get_pos(shift)?;
-                self.get_val(pos)
+                let pos = self.address(self.offset(&raw)?)?;
+                Ok(self.get_val(pos))
             }
-            OpMode::Immediate => self.get_val(self.pos + shift),
+            OpMode::Immediate => Ok(raw),
             OpMode::Relative => {
-                let pos = (self.get_pos(shift)? as isize + self.relative_base) as usize;
-                self.get_val(pos)
+                let pos = self.address(self.relative(&raw)?)?;
+                Ok(self.get_val(pos))
             }
         }
     }
 
     fn get_param_write(&mut self, shift: usize, mode: OpMode) -> Result<usize, ExecutionError> {
-        let loc = self.get_pos(shift)?;
+        let raw = self.mem.get(self.pos + shift);
         match mode {
-            OpMode::Immediate => Err("input parameter cannot be in immediate mode".into()),
-            OpMode::Position => Ok(loc),
-            OpMode::Relative => Ok((loc as isize + self.relative_base) as usize),
+            OpMode::Immediate => Err(ExecutionError::WriteInImmediateMode {
+                addr: self.pos,
+                instruction: self.instruction(),
+            }),
+            OpMode::Position => self.address(self.offset(&raw)?),
+            OpMode::Relative => self.address(self.relative(&raw)?),
         }
     }
 
-    fn get_pos(&mut self, shift: usize) -> Result<usize, PositionNotFoundError> {
-        let val = self.mem.get(&(self.pos + shift));
+    /// checks a computed address on behalf of the current instruction
+    fn address(&self, target: isize) -> Result<usize, ExecutionError> {
+        if target < 0 {
+            return Err(ExecutionError::NegativeAddress {
+                addr: self.pos,
+                instruction: self.instruction(),
+                target,
+            });
+        }
+        Ok(target as usize)
+    }
 
-        match val {
-            Some(v) => Ok(*v as usize),
-            None => {
-                self.mem.insert(self.pos + shift, 0);
-                Ok(0)
+    /// a cell used as an address or relative base offset
+    fn offset(&self, v: &T) -> Result<isize, ExecutionError> {
+        v.to_isize().ok_or(ExecutionError::Overflow {
+            addr: self.pos,
+            instruction: self.instruction(),
+        })
+    }
+
+    /// `v` added to the relative base
+    fn relative(&self, v: &T) -> Result<isize, ExecutionError> {
+        self.offset(v)?
+            .checked_add(self.relative_base)
+            .ok_or(ExecutionError::Overflow {
+                addr: self.pos,
+                instruction: self.instruction(),
+            })
+    }
+
+    /// the cell under the instruction pointer, cells too wide for an `isize`
+    /// read as `isize::MAX`, which is not a valid instruction either
+    fn instruction(&self) -> isize {
+        self.mem.get(self.pos).to_isize().unwrap_or(isize::MAX)
+    }
+
+    fn arith(
+        &self,
+        a: &T,
+        b: &T,
+        checked: fn(&T, &T) -> Option<T>,
+        wrapping: fn(&T, &T) -> T,
+    ) -> Result<T, ExecutionError> {
+        if !self.checked {
+            return Ok(wrapping(a, b));
+        }
+        checked(a, b).ok_or(ExecutionError::Overflow {
+            addr: self.pos,
+            instruction: self.instruction(),
+        })
+    }
+
+    pub fn get_val(&mut self, pos: usize) -> T {
+        let val = self.mem.get(pos);
+        self.trace(Access::Read, pos, &val);
+        val
+    }
+
+    /// writes to memory from outside the program, which a recording notes
+    pub fn set(&mut self, pos: usize, val: T) {
+        if let Some(rec) = self.recorder.as_mut() {
+            rec.record(Io::Set(pos, val.clone()));
+        }
+        self.store(pos, val);
+    }
+
+    fn store(&mut self, pos: usize, val: T) {
+        self.trace(Access::Write, pos, &val);
+        if self.history.is_some() || self.guard.is_some() {
+            let old = self.mem.get(pos);
+            if let Some(g) = self.guard.as_mut() {
+                g.write(pos, &old, &val);
+            }
+            if let Some(h) = self.history.as_mut() {
+                h.write(pos, old);
             }
         }
+        if let Some(c) = self.cache.as_mut() {
+            c.invalidate(pos);
+        }
+        self.mem.set(pos, val);
+    }
+
+    fn trace(&mut self, access: Access, addr: usize, value: &T) {
+        if let Some(t) = self.tracer.as_mut() {
+            t.record(Record {
+                ip: self.pos,
+                access,
+                addr,
+                value: value.clone(),
+            });
+        }
     }
 
-    pub fn get_val(&mut self, pos: usize) -> Result<isize, PositionNotFoundError> {
-        let val = self.mem.get(&pos);
+    /// starts recording memory accesses, keeping any tracer already in place
+    pub fn enable_tracing(&mut self) -> &mut Tracer<T> {
+        self.tracer.get_or_insert_with(|| Box::new(Tracer::new()))
+    }
 
-        match val {
-            Some(v) => Ok(*v),
-            None => {
-                self.mem.insert(pos, 0);
-                Ok(0)
+    pub fn tracer(&self) -> Option<&Tracer<T>> {
+        self.tracer.as_deref()
+    }
+
+    /// the access that triggered a watchpoint, clearing it so execution can resume
+    pub fn take_watch_hit(&mut self) -> Option<Record<T>> {
+        self.tracer.as_mut().and_then(|t| t.take_hit())
+    }
+
+    /// Stops the machine with `StopEvent::LimitExceeded`, or the matching
+    /// error from `step`, once it runs into `limits`. Replaces earlier limits
+    /// and restarts the step count and the clock.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.guard = if limits.is_unlimited() {
+            None
+        } else {
+            Some(Box::new(Guard::new(limits, &self.mem)))
+        };
+    }
+
+    /// the limits set with `set_limits`
+    pub fn limits(&self) -> Limits {
+        self.guard
+            .as_ref()
+            .map(|g| g.limits().clone())
+            .unwrap_or_default()
+    }
+
+    /// Counts executed instructions, adding them to `profile` when this
+    /// machine, or any clone made from now on, is dropped.
+    pub fn enable_profiling(&mut self, profile: Arc<Mutex<Profile>>) {
+        if let Some(p) = self.profiler.as_mut() {
+            p.flush();
+        }
+        self.profiler = Some(Box::new(Profiler::new(profile)));
+    }
+
+    /// Records the inputs, outputs and outside writes of this machine into
+    /// `log` when it, or any clone made from now on, is dropped. See
+    /// `IoLog::replay` to check a machine still behaves the same.
+    pub fn enable_recording(&mut self, log: Arc<Mutex<IoLog<T>>>) {
+        if let Some(r) = self.recorder.as_mut() {
+            r.flush();
+        }
+        self.recorder = Some(Box::new(Recorder::new(log)));
+    }
+
+    /// Starts keeping an undo log so execution can be rewound, with a
+    /// checkpoint every `interval` steps and at most `max_checkpoints` kept.
+    pub fn enable_history(
+        &mut self,
+        interval: usize,
+        max_checkpoints: usize,
+    ) -> &mut History<T, M> {
+        self.history
+            .get_or_insert_with(|| Box::new(History::new(interval, max_checkpoints)))
+    }
+
+    pub fn history(&self) -> Option<&History<T, M>> {
+        self.history.as_deref()
+    }
+
+    /// undoes the last `n` recorded steps, or all of them if there are fewer
+    pub fn rewind(&mut self, n: usize) -> Rewound<T> {
+        let rewound = match self.history.as_mut() {
+            Some(h) => h.rewind(n, &mut self.mem, &mut self.pos, &mut self.relative_base),
+            None => Rewound {
+                steps: 0,
+                inputs: vec![],
+            },
+        };
+        if let Some(g) = self.guard.as_mut() {
+            g.reset_loops(&self.mem);
+        }
+        if self.cache.is_some() {
+            self.cache = Some(OpCache::translate(&self.mem, &self.dialect));
+        }
+        rewound
+    }
+
+    /// rewinds to just before the last recorded step that wrote to `addr`
+    pub fn rewind_to_write(&mut self, addr: usize) -> Option<Rewound<T>> {
+        let n = self.history.as_ref()?.since_write(addr)?;
+        Some(self.rewind(n))
+    }
+
+    /// reads memory without recording the access, unlike `get_val`
+    pub fn peek(&self, pos: usize) -> T {
+        self.mem.get(pos)
+    }
+
+    /// contiguous copy of memory from address 0 up to the highest address written
+    pub fn memory(&self) -> Vec<T> {
+        self.mem.to_vec()
+    }
+
+    /// the allocated runs of memory, see `Store::segments`
+    pub fn segments(&self) -> Vec<(usize, Vec<T>)> {
+        self.mem.segments()
+    }
+}
+
+/// The step-at-a-time API from before `Coroutine` and `run`, which the days
+/// no longer use.
+#[allow(dead_code)]
+impl<T: Cell> IntcodeComputer<T> {
+    /// Runs until the machine halts, outputs a value or needs input. Also
+    /// returns `(false, None)` once a watchpoint was touched, see `take_watch_hit`.
+    pub fn step_pause_on_io(&mut self) -> Result<(bool, Option<T>), ExecutionError> {
+        loop {
+            if self.tracer.as_ref().is_some_and(|t| t.has_hit()) {
+                return Ok((false, None));
+            }
+            match self.step(None) {
+                Ok((true, _)) => return Ok((true, None)),
+                Ok((false, Some(v))) => return Ok((false, Some(v))),
+                Ok((false, None)) => {}
+                Err(ExecutionError::MissingInput { .. }) => return Ok((false, None)),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// runs until the machine halts or needs input, collecting its outputs
+    pub fn accumulate_output_until_action(
+        &mut self,
+    ) -> Result<(Vec<T>, StopEvent<T>), ExecutionError> {
+        let mut outputs = Vec::new();
+        for y in Coroutine::new(&mut *self) {
+            match y {
+                Ok(Yield::Output(v)) => outputs.push(v),
+                Ok(Yield::NeedInput) => return Ok((outputs, StopEvent::WaitingOnInput)),
+                Ok(Yield::Watchpoint(hit)) => return Ok((outputs, StopEvent::Watchpoint(hit))),
+                Err(ExecutionError::LimitExceeded(l)) => {
+                    return Ok((outputs, StopEvent::LimitExceeded(l)))
+                }
+                Err(e) => return Err(e),
             }
         }
+        Ok((outputs, StopEvent::Finished))
     }
+}
 
-    pub fn set(&mut self, pos: usize, val: isize) {
-        self.mem.insert(pos, val);
+#[allow(dead_code)]
+impl IntcodeComputer {
+    pub fn should_stop_on_input(&mut self) -> Result<bool, ExecutionError> {
+        match self.step_pause_on_io()? {
+            (_, Some(v)) => Err(ExecutionError::UnexpectedOutput(v)),
+            (finished, None) => Ok(finished),
+        }
+    }
+}
+
+impl IntcodeComputer {
+    /// decodes the instruction under the instruction pointer
+    pub fn current_instruction(&self) -> Option<disasm::Instruction> {
+        self.instruction_at(self.pos)
+    }
+
+    /// decodes the instruction at `addr`, without tracing the reads
+    pub fn instruction_at(&self, addr: usize) -> Option<disasm::Instruction> {
+        let window = (0..4).map(|i| self.peek(addr + i)).collect::<Vec<_>>();
+        disasm::decode(&window, 0).map(|ins| disasm::Instruction { addr, ..ins })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use num_bigint::BigInt;
+    use std::collections::VecDeque;
+
     #[test]
     fn test_io() {
         let mut com = new(vec![3, 0, 4, 0, 99]);
@@ -303,10 +758,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_errors() {
+        let err = new(vec![1, 0, 0, 0, 42]).accumulate_output_until_action();
+        assert_eq!(
+            err.unwrap_err(),
+            ExecutionError::UnknownOpcode {
+                addr: 4,
+                instruction: 42
+            }
+        );
+
+        let err = new(vec![301, 0, 0, 0]).step(None).unwrap_err();
+        assert_eq!(
+            err,
+            ExecutionError::InvalidMode {
+                addr: 0,
+                instruction: 301
+            }
+        );
+
+        let err = new(vec![1101, 1, 1, 0, 11101, 1, 1, 0]).accumulate_output_until_action();
+        assert_eq!(
+            err.unwrap_err(),
+            ExecutionError::WriteInImmediateMode {
+                addr: 4,
+                instruction: 11101
+            }
+        );
+
+        let err = new(vec![109, -5, 204, 1, 99])
+            .accumulate_output_until_action()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ExecutionError::NegativeAddress {
+                addr: 2,
+                instruction: 204,
+                target: -4
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "instruction 204 at 2 accesses negative address -4"
+        );
+
+        let err = new(vec![3, 0, 99]).step(None).unwrap_err();
+        assert_eq!(err, ExecutionError::MissingInput { addr: 0 });
+        assert_eq!(new(vec![3, 0, 99]).should_stop_on_input(), Ok(false));
+
+        // the relative base may not leave isize behind
+        let err = new(vec![109, isize::MAX, 109, 1, 99])
+            .run(&mut VecDeque::new(), &mut vec![])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ExecutionError::Overflow {
+                addr: 2,
+                instruction: 109
+            }
+        );
+    }
+
     #[test]
     fn test_day9() {
-        // let mut com = new(vec![104, 1125899906842624, 99]);
-        // assert_eq!(com.should_output().unwrap(), 1125899906842624);
+        let mut com = new(vec![104, 1125899906842624, 99]);
+        assert_eq!(
+            com.step_pause_on_io().unwrap(),
+            (false, Some(1125899906842624))
+        );
 
         let mut com = new(vec![
             109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
@@ -318,4 +838,116 @@ mod tests {
             vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99]
         )
     }
+
+    #[test]
+    fn test_cell_types() {
+        // outputs the input to the fourth power
+        let prog = vec![3, 0, 2, 0, 0, 0, 2, 0, 0, 0, 4, 0, 99];
+        let run =
+            |com: &mut IntcodeComputer| com.run(&mut VecDeque::from(vec![1 << 20]), &mut vec![]);
+
+        assert!(run(&mut new(prog.clone())).is_ok());
+        let mut checked = new(prog.clone());
+        checked.check_overflow(true);
+        assert_eq!(
+            run(&mut checked).unwrap_err(),
+            ExecutionError::Overflow {
+                addr: 6,
+                instruction: 2
+            }
+        );
+
+        let mut wide = from_cells(prog.iter().map(|v| *v as i128).collect());
+        wide.check_overflow(true);
+        let mut out = vec![];
+        wide.run(&mut VecDeque::from(vec![1 << 20]), &mut out)
+            .unwrap();
+        assert_eq!(out, vec![1 << 80]);
+
+        let mut big = from_cells(prog.iter().map(|v| BigInt::from(*v)).collect());
+        big.check_overflow(true);
+        let mut out = vec![];
+        big.run(&mut VecDeque::from(vec![BigInt::from(1) << 40]), &mut out)
+            .unwrap();
+        assert_eq!(out, vec![BigInt::from(1) << 160]);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // reads opcode cells for the `out` until it reads a 0
+        let prog = asm::assemble(
+            "
+            loop:   in [x]
+                    jz [x], #end
+                    add [x], #0, [op]
+            op:     out [x]
+                    jz #0, #loop
+            end:    hlt
+            x:      data 0
+            ",
+        )
+        .unwrap();
+        let run = |com: &mut IntcodeComputer| {
+            let mut out = vec![];
+            com.run(&mut VecDeque::from(vec![4, 104, 4, 0]), &mut out)
+                .unwrap();
+            out
+        };
+
+        let mut decoding = new(prog.clone());
+        decoding.use_op_cache(false);
+        assert_eq!(run(&mut decoding), vec![4, 15, 4]);
+        let mut com = new(prog);
+        com.enable_history(4, 100);
+        assert_eq!(run(&mut com), vec![4, 15, 4]);
+
+        // rewinding restores the original opcode behind the cache's back
+        com.rewind(1000);
+        assert_eq!(run(&mut com), vec![4, 15, 4]);
+    }
+
+    #[test]
+    fn test_with_store() {
+        // the quine from day 9, then a write far out
+        let mut com = new(vec![
+            109,
+            1,
+            204,
+            -1,
+            1001,
+            100,
+            1,
+            100,
+            1008,
+            100,
+            16,
+            101,
+            1006,
+            101,
+            0,
+            1101,
+            7,
+            8,
+            1 << 40,
+            99,
+        ]);
+        com.use_op_cache(false);
+        let mut hashed = com.with_store::<HashMemory>();
+
+        let mut out = vec![];
+        let mut hashed_out = vec![];
+        assert_eq!(
+            com.run(&mut VecDeque::new(), &mut out),
+            Ok(StopEvent::Finished)
+        );
+        assert_eq!(
+            hashed.run(&mut VecDeque::new(), &mut hashed_out),
+            Ok(StopEvent::Finished)
+        );
+        assert_eq!(out, hashed_out);
+        assert_eq!(out.len(), 16);
+        assert_eq!(com.peek(1 << 40), 15);
+        assert_eq!(hashed.peek(1 << 40), 15);
+        assert!((0..20).all(|a| hashed.peek(a) == com.peek(a)));
+    }
 }
@@ -0,0 +1,118 @@
+use std::{collections::VecDeque, fmt};
+
+use super::{ExecutionError, IntcodeComputer, StopEvent};
+
+/// A run of output from a machine talking ASCII.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Chunk {
+    Text(String),
+    /// a value outside of ASCII, usually a puzzle answer
+    Value(isize),
+}
+
+/// text as it is, values on a line of their own
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chunk::Text(s) => write!(f, "{}", s),
+            Chunk::Value(v) => writeln!(f, "{}", v),
+        }
+    }
+}
+
+/// character codes of `line` followed by a newline
+pub fn encode(line: &str) -> Vec<isize> {
+    line.bytes()
+        .chain(std::iter::once(b'\n'))
+        .map(|b| b as isize)
+        .collect()
+}
+
+/// splits output values into text and the values that are not ASCII
+pub fn decode(values: &[isize]) -> Vec<Chunk> {
+    let mut chunks = vec![];
+    for v in values.iter().copied() {
+        match (chunks.last_mut(), v) {
+            (Some(Chunk::Text(s)), 0..=127) => s.push(v as u8 as char),
+            (_, 0..=127) => chunks.push(Chunk::Text((v as u8 as char).to_string())),
+            _ => chunks.push(Chunk::Value(v)),
+        }
+    }
+    chunks
+}
+
+/// Wraps a machine so it can be talked to in lines of text.
+pub struct Ascii {
+    com: IntcodeComputer,
+    input: VecDeque<isize>,
+}
+
+impl Ascii {
+    pub fn new(com: IntcodeComputer) -> Ascii {
+        Ascii {
+            com,
+            input: VecDeque::new(),
+        }
+    }
+
+    /// queues `line` for the machine to read, see `encode`
+    pub fn send_line(&mut self, line: &str) {
+        self.input.extend(encode(line));
+    }
+
+    /// Runs until the machine halts or has read every queued line, returning
+    /// what it printed meanwhile.
+    pub fn run(&mut self) -> Result<(Vec<Chunk>, StopEvent), ExecutionError> {
+        let mut out = vec![];
+        let event = self.com.run(&mut self.input, &mut out)?;
+        Ok((decode(&out), event))
+    }
+
+    pub fn into_machine(self) -> IntcodeComputer {
+        self.com
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm};
+
+    // echoes one line, then outputs a number that is not a character
+    const ECHO: &str = "
+        loop:   in [c]
+                out [c]
+                eq [c], #10, [nl]
+                jz [nl], #loop
+                out #12345
+                hlt
+        c:      data 0
+        nl:     data 0
+    ";
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(encode("hi"), vec![104, 105, 10]);
+        assert_eq!(
+            decode(&[72, 105, 10, 2000, 33, -1]),
+            vec![
+                Chunk::Text("Hi\n".to_string()),
+                Chunk::Value(2000),
+                Chunk::Text("!".to_string()),
+                Chunk::Value(-1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conversation() {
+        let mut ascii = Ascii::new(intcode::new(asm::assemble(ECHO).unwrap()));
+        assert_eq!(ascii.run().unwrap(), (vec![], StopEvent::WaitingOnInput));
+
+        ascii.send_line("hello");
+        let (out, event) = ascii.run().unwrap();
+        assert_eq!(event, StopEvent::Finished);
+        let text = out.iter().map(|c| c.to_string()).collect::<String>();
+        assert_eq!(text, "hello\n12345\n");
+    }
+}
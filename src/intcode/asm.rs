@@ -0,0 +1,275 @@
+use std::{collections::HashMap, error::Error, fmt};
+
+use super::modes::OpMode;
+use super::opcode::Opcode;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(isize),
+    Label(String, isize),
+}
+
+#[derive(Debug)]
+enum Item {
+    Ins(Opcode, Vec<(OpMode, Expr)>),
+    Data(Vec<Expr>),
+}
+
+/// Assembles mnemonic source into a program.
+///
+/// Each line is `[label:] [mnemonic operands | data values] [; comment]`.
+/// Operands are `#v` (immediate), `[v]` (position) or `[r+v]` (relative),
+/// where `v` is a number, a label or `label+offset`. A numeric label such as
+/// `12:` asserts the current address, so disassembler output assembles back.
+pub fn assemble(src: &str) -> Result<Vec<isize>, AsmError> {
+    let mut labels: HashMap<String, isize> = HashMap::new();
+    let mut items: Vec<(usize, Item)> = vec![];
+    let mut addr = 0;
+
+    for (i, raw) in src.lines().enumerate() {
+        let line_no = i + 1;
+        let err = |msg: String| AsmError { line: line_no, msg };
+
+        let mut rest = raw.split(';').next().unwrap_or("").trim();
+        while let Some(idx) = rest.find(':') {
+            let label = rest[..idx].trim();
+            if let Ok(n) = label.parse::<usize>() {
+                if n != addr {
+                    return Err(err(format!("expected address {}, at {}", n, addr)));
+                }
+            } else {
+                check_ident(label).map_err(err)?;
+                if labels.insert(label.to_string(), addr as isize).is_some() {
+                    return Err(err(format!("label {} defined twice", label)));
+                }
+            }
+            rest = rest[idx + 1..].trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (word, args) = match rest.find(char::is_whitespace) {
+            Some(idx) => (&rest[..idx], rest[idx..].trim()),
+            None => (rest, ""),
+        };
+        let args = if args.is_empty() {
+            vec![]
+        } else {
+            args.split(',').map(|a| a.trim()).collect::<Vec<_>>()
+        };
+
+        let item = if word == "data" {
+            if args.is_empty() {
+                return Err(err("data directive needs at least one value".to_string()));
+            }
+            Item::Data(
+                args.iter()
+                    .map(|a| parse_expr(a))
+                    .collect::<Result<_, _>>()
+                    .map_err(err)?,
+            )
+        } else {
+            let op = Opcode::from_mnemonic(word)
+                .ok_or_else(|| err(format!("unknown mnemonic {}", word)))?;
+            if args.len() != op.arity() {
+                return Err(err(format!(
+                    "{} takes {} operands, got {}",
+                    word,
+                    op.arity(),
+                    args.len()
+                )));
+            }
+            let params = args
+                .iter()
+                .map(|a| parse_operand(a))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(err)?;
+            if op.writes() && params[op.arity() - 1].0 == OpMode::Immediate {
+                return Err(err(format!(
+                    "{} cannot write to an immediate operand",
+                    word
+                )));
+            }
+            Item::Ins(op, params)
+        };
+
+        addr += match &item {
+            Item::Ins(op, _) => op.size(),
+            Item::Data(d) => d.len(),
+        };
+        items.push((line_no, item));
+    }
+
+    let mut out = Vec::with_capacity(addr);
+    for (line_no, item) in items {
+        let resolve = |e: &Expr| -> Result<isize, AsmError> {
+            match e {
+                Expr::Num(n) => Ok(*n),
+                Expr::Label(l, off) => labels.get(l).map(|a| a + off).ok_or_else(|| AsmError {
+                    line: line_no,
+                    msg: format!("undefined label {}", l),
+                }),
+            }
+        };
+        match item {
+            Item::Ins(op, params) => {
+                let modes = params.iter().rev().fold(0, |acc, (m, _)| {
+                    acc * 10
+                        + match m {
+                            OpMode::Position => 0,
+                            OpMode::Immediate => 1,
+                            OpMode::Relative => 2,
+                        }
+                });
+                out.push(modes * 100 + op.code());
+                for (_, e) in params.iter() {
+                    out.push(resolve(e)?);
+                }
+            }
+            Item::Data(values) => {
+                for e in values.iter() {
+                    out.push(resolve(e)?);
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn check_ident(s: &str) -> Result<(), String> {
+    let valid = matches!(s.chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid || s == "r" {
+        return Err(format!("invalid label {:?}", s));
+    }
+    Ok(())
+}
+
+fn parse_operand(s: &str) -> Result<(OpMode, Expr), String> {
+    if let Some(v) = s.strip_prefix('#') {
+        return Ok((OpMode::Immediate, parse_expr(v)?));
+    }
+    let inner = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or_else(|| format!("cannot parse operand {:?}", s))?
+        .trim();
+    match inner.strip_prefix('r') {
+        Some("") => Ok((OpMode::Relative, Expr::Num(0))),
+        Some(off) if off.trim_start().starts_with(['+', '-'].as_ref()) => {
+            let off = off.trim_start();
+            let e = parse_expr(off[1..].trim())?;
+            Ok((
+                OpMode::Relative,
+                if off.starts_with('-') { negate(e) } else { e },
+            ))
+        }
+        _ => Ok((OpMode::Position, parse_expr(inner)?)),
+    }
+}
+
+fn negate(e: Expr) -> Expr {
+    match e {
+        Expr::Num(n) => Expr::Num(-n),
+        Expr::Label(l, off) => Expr::Label(l, -off),
+    }
+}
+
+fn parse_expr(s: &str) -> Result<Expr, String> {
+    let s = s.trim();
+    if let Ok(n) = s.parse::<isize>() {
+        return Ok(Expr::Num(n));
+    }
+    let (label, off) = match s.rfind(['+', '-'].as_ref()) {
+        Some(idx) if idx > 0 => {
+            let off = s[idx..]
+                .replace(' ', "")
+                .trim_start_matches('+')
+                .parse::<isize>()
+                .map_err(|e| format!("cannot parse offset in {:?}: {}", s, e))?;
+            (s[..idx].trim(), off)
+        }
+        _ => (s, 0),
+    };
+    check_ident(label)?;
+    Ok(Expr::Label(label.to_string(), off))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, disasm};
+
+    #[test]
+    fn test_echo() {
+        let prog = assemble("in [0]\nout [0]\nhlt").unwrap();
+        assert_eq!(prog, vec![3, 0, 4, 0, 99]);
+    }
+
+    #[test]
+    fn test_labels_and_data() {
+        let src = "
+            ; count down from 3, printing each value
+            loop:   out [n]
+                    add [n], #-1, [n]
+                    jnz [n], #loop
+                    hlt
+            n:      data 3
+            table:  data n, n+1, end - 1
+            end:
+        ";
+        let prog = assemble(src).unwrap();
+        assert_eq!(
+            prog,
+            vec![4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3, 10, 11, 13]
+        );
+
+        let mut com = intcode::new(prog);
+        let (out, _) = com.accumulate_output_until_action().unwrap();
+        assert_eq!(out, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_relative() {
+        let prog = assemble("arb #5\nout [r-1]\nout [r]\nout [r + 2]\nhlt").unwrap();
+        assert_eq!(prog, vec![109, 5, 204, -1, 204, 0, 204, 2, 99]);
+    }
+
+    #[test]
+    fn test_disasm_round_trip() {
+        let prog = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let listing = disasm::disassemble(&prog)
+            .iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(assemble(&listing).unwrap(), prog);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(assemble("hlt\nfoo [1]").unwrap_err().line, 2);
+        assert!(assemble("add #1, #2, #3").is_err());
+        assert!(assemble("add #1, #2").is_err());
+        assert!(assemble("jz #0, #nowhere").is_err());
+        assert!(assemble("a: hlt\na: hlt").is_err());
+        assert!(assemble("1: hlt").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use super::{cell::Cell, dialect::Dialect, memory::Store, modes::Decoded};
+
+/// instructions at higher addresses are decoded on every execution, so a
+/// jump far out does not grow the table without bound
+const MAX_CACHED: usize = 1 << 16;
+
+/// Decoded instructions by address, so a loop pays for splitting its opcode
+/// cells once instead of on every pass. The table is shared between clones
+/// until one of them changes it, and only holds the opcode cells: operands
+/// are still read from memory, so only a write to an opcode cell drops an
+/// entry.
+#[derive(Clone, Default)]
+pub(super) struct OpCache {
+    ops: Arc<Vec<Option<Decoded>>>,
+}
+
+impl OpCache {
+    /// Translates the program in `mem` ahead of time, walking it from address
+    /// 0 as one instruction after another. Whatever this gets wrong, data
+    /// read as code or code hidden behind data, costs at most a decode later.
+    pub(super) fn translate<T: Cell, M: Store<T>>(mem: &M, dialect: &Dialect<T, M>) -> OpCache {
+        let len = mem.extent().min(MAX_CACHED);
+        let mut ops = vec![None; len];
+        let mut addr = 0;
+        while addr < len {
+            match mem.get(addr).to_isize().map(|cell| dialect.decode(cell)) {
+                Some(Ok(d)) => {
+                    ops[addr] = Some(d);
+                    addr += d.size();
+                }
+                _ => addr += 1,
+            }
+        }
+        OpCache { ops: Arc::new(ops) }
+    }
+
+    #[inline]
+    pub(super) fn get(&self, addr: usize) -> Option<Decoded> {
+        self.ops.get(addr).copied().flatten()
+    }
+
+    pub(super) fn insert(&mut self, addr: usize, d: Decoded) {
+        if addr >= MAX_CACHED {
+            return;
+        }
+        let ops = Arc::make_mut(&mut self.ops);
+        if addr >= ops.len() {
+            ops.resize(addr + 1, None);
+        }
+        ops[addr] = Some(d);
+    }
+
+    /// forgets the instruction at `addr` after a write to it
+    #[inline]
+    pub(super) fn invalidate(&mut self, addr: usize) {
+        if self.get(addr).is_some() {
+            Arc::make_mut(&mut self.ops)[addr] = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{memory::Memory, opcode::Opcode};
+
+    #[test]
+    fn test_translate() {
+        // day 2 example: two instructions, a halt, then data
+        let mem = Memory::<isize>::from(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        let mut cache = OpCache::translate(&mem, &Dialect::standard());
+        let cached = (0..12)
+            .filter(|a| cache.get(*a).is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(cached, vec![0, 4, 8]);
+        assert_eq!(cache.get(4).unwrap().op(), Some(Opcode::Mul));
+
+        let shared = cache.clone();
+        cache.invalidate(4);
+        assert_eq!(cache.get(4), None);
+        assert!(shared.get(4).is_some());
+
+        cache.insert(MAX_CACHED, Decoded::new(99).unwrap());
+        assert_eq!(cache.get(MAX_CACHED), None);
+    }
+}
@@ -0,0 +1,102 @@
+use num_bigint::BigInt;
+use std::{convert::TryFrom, fmt, hash::Hash, str::FromStr};
+
+/// A value held in one memory cell of an Intcode machine.
+///
+/// Opcodes, addresses and relative base offsets have to fit in an `isize`
+/// whatever the cell type, only the arithmetic happens at full width.
+pub trait Cell:
+    Clone + Default + PartialEq + PartialOrd + Hash + fmt::Debug + fmt::Display + FromStr + Send + Sync
+{
+    fn from_isize(v: isize) -> Self;
+    fn to_isize(&self) -> Option<isize>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+}
+
+macro_rules! primitive_cell {
+    ($($t:ty),*) => {
+        $(impl Cell for $t {
+            #[inline]
+            fn from_isize(v: isize) -> Self {
+                v as $t
+            }
+
+            #[inline]
+            fn to_isize(&self) -> Option<isize> {
+                isize::try_from(*self).ok()
+            }
+
+            #[inline]
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            #[inline]
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_mul(*self, *other)
+            }
+
+            #[inline]
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$t>::wrapping_add(*self, *other)
+            }
+
+            #[inline]
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                <$t>::wrapping_mul(*self, *other)
+            }
+        })*
+    };
+}
+
+primitive_cell!(isize, i64, i128);
+
+/// never overflows, so checked and wrapping arithmetic are the same
+impl Cell for BigInt {
+    fn from_isize(v: isize) -> Self {
+        BigInt::from(v)
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        isize::try_from(self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_widths() {
+        let big = i64::MAX;
+        assert_eq!(Cell::checked_add(&big, &1), None);
+        assert_eq!(Cell::wrapping_add(&big, &1), i64::MIN);
+        assert_eq!(Cell::checked_mul(&(big as i128), &2), Some(2 * big as i128));
+        assert_eq!((1i128 << 80).to_isize(), None);
+
+        let b = BigInt::from_isize(isize::MAX);
+        let square = b.checked_mul(&b).unwrap();
+        assert_eq!(square.to_string(), "85070591730234615847396907784232501249");
+        assert_eq!(square.to_isize(), None);
+        assert_eq!(b.to_isize(), Some(isize::MAX));
+    }
+}
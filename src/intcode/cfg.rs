@@ -0,0 +1,441 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use super::{
+    disasm::{self, Instruction},
+    modes::OpMode,
+    opcode::Opcode,
+};
+
+/// A straight run of instructions only ever entered at its first one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Block {
+    /// address right after the last instruction
+    pub fn end(&self) -> usize {
+        self.instructions
+            .last()
+            .map_or(self.start, |ins| ins.addr + ins.size())
+    }
+
+    pub fn last(&self) -> &Instruction {
+        self.instructions.last().expect("blocks are never empty")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// falls through into the next block
+    Next,
+    /// unconditional jump
+    Jump,
+    Taken,
+    NotTaken,
+    Call,
+    /// from a call to where the callee returns to
+    AfterCall,
+    Return,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// A subroutine found by the calls made to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub entry: usize,
+    /// cells the function reserves with an `arb` on entry
+    pub frame: Option<isize>,
+    /// start addresses of the blocks reachable from the entry without calling out
+    pub blocks: BTreeSet<usize>,
+    /// start addresses of the blocks that return
+    pub returns: BTreeSet<usize>,
+}
+
+/// a call as laid down by the usual Intcode compilers: the return address is
+/// stored on the stack with an `add`/`mul` of two immediates, then an
+/// unconditional jump goes to the callee
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Call {
+    target: usize,
+    ret: usize,
+}
+
+/// Control-flow graph of a program as far as it can be told without running
+/// it. Jumps through memory are followed only when they return from a call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    pub edges: Vec<Edge>,
+    pub functions: BTreeMap<usize, Function>,
+}
+
+impl Cfg {
+    pub fn build(prog: &[isize]) -> Cfg {
+        let code = discover(prog);
+        let calls = code
+            .values()
+            .filter_map(|ins| call_at(&code, ins).map(|c| (ins.addr, c)))
+            .collect::<BTreeMap<_, _>>();
+        let blocks = split_blocks(&code, &calls);
+
+        let mut edges = vec![];
+        for block in blocks.values() {
+            let last = block.last();
+            let next = block.end();
+            if let Some(call) = calls.get(&last.addr) {
+                edges.push(edge(block.start, call.target, EdgeKind::Call));
+                edges.push(edge(block.start, call.ret, EdgeKind::AfterCall));
+                continue;
+            }
+            match (last.op, known_condition(last)) {
+                (Opcode::Hlt, _) => {}
+                (Opcode::Jnz, Some(true)) | (Opcode::Jz, Some(false)) => edges.extend(
+                    last.static_target()
+                        .map(|t| edge(block.start, t, EdgeKind::Jump)),
+                ),
+                (Opcode::Jnz, Some(false)) | (Opcode::Jz, Some(true)) => {
+                    edges.push(edge(block.start, next, EdgeKind::Next))
+                }
+                (Opcode::Jnz, None) | (Opcode::Jz, None) => {
+                    edges.extend(
+                        last.static_target()
+                            .map(|t| edge(block.start, t, EdgeKind::Taken)),
+                    );
+                    edges.push(edge(block.start, next, EdgeKind::NotTaken));
+                }
+                _ => {
+                    if blocks.contains_key(&next) {
+                        edges.push(edge(block.start, next, EdgeKind::Next));
+                    }
+                }
+            }
+        }
+
+        let mut functions = BTreeMap::new();
+        for entry in calls.values().map(|c| c.target).collect::<BTreeSet<_>>() {
+            if let Some(f) = function_at(entry, &blocks, &edges) {
+                functions.insert(entry, f);
+            }
+        }
+        for f in functions.values() {
+            for from in f.returns.iter() {
+                calls
+                    .values()
+                    .filter(|c| c.target == f.entry)
+                    .for_each(|c| edges.push(edge(*from, c.ret, EdgeKind::Return)));
+            }
+        }
+        edges.sort_by_key(|e| (e.from, e.to));
+        edges.dedup();
+
+        Cfg {
+            blocks,
+            edges,
+            functions,
+        }
+    }
+
+    /// Writes the graph in Graphviz DOT, one cluster per function.
+    pub fn write_dot<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "digraph cfg {{")?;
+        writeln!(w, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        let mut placed = BTreeSet::new();
+        for f in self.functions.values() {
+            writeln!(w, "    subgraph cluster_{} {{", f.entry)?;
+            match f.frame {
+                Some(n) => writeln!(w, "        label=\"fn {} (frame {})\";", f.entry, n)?,
+                None => writeln!(w, "        label=\"fn {}\";", f.entry)?,
+            }
+            // a block shared by several functions can only be drawn in one
+            for start in f.blocks.iter().filter(|b| placed.insert(**b)) {
+                self.write_node(&mut w, &self.blocks[start], "        ")?;
+            }
+            writeln!(w, "    }}")?;
+        }
+        for block in self.blocks.values().filter(|b| !placed.contains(&b.start)) {
+            self.write_node(&mut w, block, "    ")?;
+        }
+
+        for e in self.edges.iter() {
+            let style = match e.kind {
+                EdgeKind::Next | EdgeKind::Jump => "",
+                EdgeKind::Taken => " [label=\"taken\", color=darkgreen]",
+                EdgeKind::NotTaken => " [label=\"not taken\", color=red]",
+                EdgeKind::Call => " [label=\"call\", color=blue]",
+                EdgeKind::AfterCall => " [style=dashed]",
+                EdgeKind::Return => " [label=\"ret\", style=dotted, color=blue]",
+            };
+            writeln!(w, "    b{} -> b{}{};", e.from, e.to, style)?;
+        }
+        writeln!(w, "}}")
+    }
+
+    fn write_node<W: Write>(&self, w: &mut W, block: &Block, indent: &str) -> io::Result<()> {
+        let label = block
+            .instructions
+            .iter()
+            .map(|ins| format!("{}: {}\\l", ins.addr, ins))
+            .collect::<String>();
+        let shape = match block.last().op {
+            Opcode::Hlt => ", peripheries=2",
+            _ => "",
+        };
+        writeln!(
+            w,
+            "{}b{} [label=\"{}\"{}];",
+            indent, block.start, label, shape
+        )
+    }
+}
+
+fn edge(from: usize, to: usize, kind: EdgeKind) -> Edge {
+    Edge { from, to, kind }
+}
+
+/// whether a jump's condition holds, if it is an immediate
+fn known_condition(ins: &Instruction) -> Option<bool> {
+    match ins.params.first() {
+        Some((OpMode::Immediate, v)) => Some(*v != 0),
+        _ => None,
+    }
+}
+
+fn is_unconditional(ins: &Instruction) -> bool {
+    match ins.op {
+        Opcode::Jnz => known_condition(ins) == Some(true),
+        Opcode::Jz => known_condition(ins) == Some(false),
+        _ => false,
+    }
+}
+
+/// the call made by `ins`, if it is the jump of the call idiom
+fn call_at(code: &BTreeMap<usize, Instruction>, ins: &Instruction) -> Option<Call> {
+    if !is_unconditional(ins) {
+        return None;
+    }
+    let target = ins.static_target()?;
+    let (_, store) = code.range(..ins.addr).next_back()?;
+    if store.addr + store.size() != ins.addr {
+        return None;
+    }
+    let ret = match &store.params[..] {
+        [(OpMode::Immediate, a), (OpMode::Immediate, b), (OpMode::Relative, _)] => match store.op {
+            Opcode::Add => a + b,
+            Opcode::Mul => a * b,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if ret < 0 {
+        return None;
+    }
+    Some(Call {
+        target,
+        ret: ret as usize,
+    })
+}
+
+/// whether `ins` returns from a call, jumping to an address kept on the stack
+fn is_return(ins: &Instruction) -> bool {
+    matches!(ins.op, Opcode::Jnz | Opcode::Jz) && ins.params[1].0 == OpMode::Relative
+}
+
+/// Walks every path from address 0 like `disasm::trace_reachable`, but also
+/// resumes after calls.
+fn discover(prog: &[isize]) -> BTreeMap<usize, Instruction> {
+    let mut found = BTreeMap::new();
+    let mut tried = BTreeSet::new();
+    let mut todo = vec![0];
+    while !todo.is_empty() {
+        while let Some(addr) = todo.pop() {
+            if !tried.insert(addr) {
+                continue;
+            }
+            if let Some(ins) = disasm::decode(prog, addr) {
+                todo.extend(ins.successors());
+                found.insert(addr, ins);
+            }
+        }
+        // a call is only recognised once both its store and its jump are known
+        todo = found
+            .values()
+            .filter_map(|ins| call_at(&found, ins))
+            .map(|c| c.ret)
+            .filter(|r| !tried.contains(r))
+            .collect();
+    }
+    found
+}
+
+fn split_blocks(
+    code: &BTreeMap<usize, Instruction>,
+    calls: &BTreeMap<usize, Call>,
+) -> BTreeMap<usize, Block> {
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for ins in code.values() {
+        leaders.extend(ins.static_target());
+        if matches!(ins.op, Opcode::Jnz | Opcode::Jz | Opcode::Hlt) {
+            leaders.insert(ins.addr + ins.size());
+        }
+    }
+    leaders.extend(calls.values().map(|c| c.ret));
+
+    let mut blocks = BTreeMap::new();
+    let mut current: Option<Block> = None;
+    for ins in code.values() {
+        let starts = current
+            .as_ref()
+            .is_none_or(|b| leaders.contains(&ins.addr) || b.end() != ins.addr);
+        if starts {
+            if let Some(b) = current.take() {
+                blocks.insert(b.start, b);
+            }
+            current = Some(Block {
+                start: ins.addr,
+                instructions: vec![],
+            });
+        }
+        current
+            .as_mut()
+            .expect("a block was just started")
+            .instructions
+            .push(ins.clone());
+    }
+    if let Some(b) = current {
+        blocks.insert(b.start, b);
+    }
+    blocks
+}
+
+fn function_at(entry: usize, blocks: &BTreeMap<usize, Block>, edges: &[Edge]) -> Option<Function> {
+    let first = blocks.get(&entry)?;
+    let frame = match first.instructions[0].params.first() {
+        Some((OpMode::Immediate, n)) if first.instructions[0].op == Opcode::Arb => Some(*n),
+        _ => None,
+    };
+
+    let mut members = BTreeSet::new();
+    let mut todo = vec![entry];
+    while let Some(b) = todo.pop() {
+        if !blocks.contains_key(&b) || !members.insert(b) {
+            continue;
+        }
+        todo.extend(
+            edges
+                .iter()
+                .filter(|e| e.from == b && e.kind != EdgeKind::Call)
+                .map(|e| e.to),
+        );
+    }
+    let returns = members
+        .iter()
+        .copied()
+        .filter(|b| is_return(blocks[b].last()))
+        .collect();
+
+    Some(Function {
+        entry,
+        frame,
+        blocks: members,
+        returns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm;
+
+    // doubles its input through a call, twice over
+    const CALLS: &str = "
+                in [x]
+                add [x], #0, [r+1]
+                add #0, #ret1, [r+0]
+                jz #0, #double
+        ret1:   add [r+1], #0, [r+1]
+                add #0, #ret2, [r+0]
+                jz #0, #double
+        ret2:   out [r+1]
+                hlt
+        double: arb #2
+                mul [r-1], #2, [r-1]
+                jnz [r-1], #done
+                out #0
+        done:   arb #-2
+                jnz #1, [r+0]
+        x:      data 0
+    ";
+
+    fn kinds(cfg: &Cfg, from: usize) -> Vec<(usize, EdgeKind)> {
+        cfg.edges
+            .iter()
+            .filter(|e| e.from == from)
+            .map(|e| (e.to, e.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks() {
+        // day 2 example, one block up to the halt
+        let cfg = Cfg::build(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(cfg.blocks.len(), 1);
+        assert_eq!(cfg.blocks[&0].end(), 9);
+        assert!(cfg.edges.is_empty());
+
+        let cfg = Cfg::build(&[3, 9, 1005, 9, 7, 104, 1, 104, 2, 0, 99]);
+        assert_eq!(
+            cfg.blocks.keys().copied().collect::<Vec<_>>(),
+            vec![0, 5, 7]
+        );
+        assert_eq!(
+            kinds(&cfg, 0),
+            vec![(5, EdgeKind::NotTaken), (7, EdgeKind::Taken)]
+        );
+        assert_eq!(kinds(&cfg, 5), vec![(7, EdgeKind::Next)]);
+    }
+
+    #[test]
+    fn test_calls() {
+        let prog = asm::assemble(CALLS).unwrap();
+        let cfg = Cfg::build(&prog);
+        assert_eq!(cfg.functions.len(), 1);
+        let f = &cfg.functions[&27];
+        assert_eq!(f.frame, Some(2));
+        assert_eq!(
+            f.blocks.iter().copied().collect::<Vec<_>>(),
+            vec![27, 36, 38]
+        );
+        assert_eq!(f.returns.iter().copied().collect::<Vec<_>>(), vec![38]);
+
+        // both return sites are found though only reached through memory
+        assert_eq!(
+            kinds(&cfg, 0),
+            vec![(13, EdgeKind::AfterCall), (27, EdgeKind::Call)]
+        );
+        assert_eq!(
+            kinds(&cfg, 38),
+            vec![(13, EdgeKind::Return), (24, EdgeKind::Return)]
+        );
+        assert!(cfg.blocks.contains_key(&24));
+
+        let mut dot = vec![];
+        cfg.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("subgraph cluster_27 {\n        label=\"fn 27 (frame 2)\";"));
+        assert!(dot.contains("b24 [label=\"24: out [r+1]\\l26: hlt\\l\", peripheries=2];"));
+        assert!(dot.contains("b0 -> b27 [label=\"call\", color=blue];"));
+    }
+}
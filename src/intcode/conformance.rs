@@ -0,0 +1,183 @@
+// Runs the published examples in `fixtures/intcode`, see the comments at
+// the top of those files for the format.
+
+use std::{collections::VecDeque, fs, path::Path};
+
+use crate::days::day7;
+
+use super::{
+    reference::{self, Stop},
+    ExecutionError, StopEvent,
+};
+
+/// one block of a fixture file
+#[derive(Default)]
+struct Case {
+    /// the comment above the block, or where it starts
+    name: String,
+    program: Vec<isize>,
+    input: Vec<isize>,
+    output: Option<Vec<isize>>,
+    memory: Option<Vec<isize>>,
+    chain: Option<Vec<isize>>,
+    feedback: Option<Vec<isize>>,
+}
+
+/// a list of values, `v*n` standing for `n` times `v`
+fn values(s: &str) -> Vec<isize> {
+    let parse = |v: &str| v.trim().parse().expect("cannot parse value");
+    s.split(',')
+        .flat_map(|v| match v.split_once('*') {
+            Some((v, n)) => vec![parse(v); parse(n) as usize],
+            None => vec![parse(v)],
+        })
+        .collect()
+}
+
+fn parse(file: &str, src: &str) -> Vec<Case> {
+    let mut cases = vec![];
+    let mut case: Option<Case> = None;
+    let mut comment = None;
+    for (n, line) in src.lines().enumerate().map(|(n, l)| (n + 1, l.trim())) {
+        if line.is_empty() {
+            cases.extend(case.take());
+            comment = None;
+            continue;
+        }
+        if let Some(c) = line.strip_prefix('#') {
+            comment = Some(c.trim().to_string());
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .unwrap_or_else(|| panic!("{}:{}: expecting key: value", file, n));
+        let c = case.get_or_insert_with(|| Case {
+            name: format!("{}:{} {}", file, n, comment.take().unwrap_or_default()),
+            // a block without a program runs the one before it
+            program: cases
+                .last()
+                .map(|c: &Case| c.program.clone())
+                .unwrap_or_default(),
+            ..Case::default()
+        });
+        match key {
+            "program" => c.program = values(value),
+            "input" => c.input = values(value),
+            "output" => c.output = Some(values(value)),
+            "memory" => c.memory = Some(values(value)),
+            "chain" => c.chain = Some(values(value)),
+            "feedback" => c.feedback = Some(values(value)),
+            _ => panic!("{}:{}: unknown key {}", file, n, key),
+        }
+    }
+    cases.extend(case);
+    cases
+}
+
+/// what a case should compare against: the outputs, and memory if asked for
+type Run = (Vec<isize>, Option<Vec<isize>>);
+
+fn run(case: &Case) -> Result<Run, ExecutionError> {
+    let com = super::new(case.program.clone());
+    if let Some(phases) = &case.chain {
+        return Ok((vec![day7::chain(&com, phases)?], None));
+    }
+    if let Some(phases) = &case.feedback {
+        return Ok((vec![day7::feedback(&com, phases)?], None));
+    }
+
+    let mut com = com;
+    let mut out = vec![];
+    match com.run(&mut VecDeque::from(case.input.clone()), &mut out)? {
+        StopEvent::Finished => Ok((out, Some(com.memory()))),
+        _ => Err(ExecutionError::UnexpectedInput),
+    }
+}
+
+/// the reference interpreter gets the cases that need only one machine
+fn run_reference(case: &Case) -> Option<Run> {
+    if case.chain.is_some() || case.feedback.is_some() {
+        return None;
+    }
+    let out = reference::run(&case.program, &case.input, 1_000_000);
+    assert_eq!(out.stop, Stop::Halted, "{}: reference", case.name);
+    let len = out.memory.keys().max().map_or(0, |a| a + 1);
+    let memory = (0..len)
+        .map(|a| out.memory.get(&a).copied().unwrap_or(0))
+        .collect();
+    Some((out.outputs, Some(memory)))
+}
+
+fn check(case: &Case, (outputs, memory): Run) -> Result<(), String> {
+    if let Some(expected) = &case.output {
+        if &outputs != expected {
+            return Err(format!("outputs {:?}, expected {:?}", outputs, expected));
+        }
+    }
+    if let (Some(expected), Some(memory)) = (&case.memory, memory) {
+        if &memory != expected {
+            return Err(format!("memory {:?}, expected {:?}", memory, expected));
+        }
+    }
+    Ok(())
+}
+
+fn run_fixture(name: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/intcode")
+        .join(name);
+    let src = fs::read_to_string(&path).expect("error reading fixture");
+    let cases = parse(name, &src);
+    assert!(!cases.is_empty(), "no cases in {}", name);
+
+    let failures = cases
+        .iter()
+        .flat_map(|case| {
+            let machine = run(case)
+                .map_err(|e| e.to_string())
+                .and_then(|r| check(case, r));
+            let reference = run_reference(case).map(|r| check(case, r));
+            vec![("machine", Some(machine)), ("reference", reference)]
+                .into_iter()
+                .filter_map(move |(who, r)| match r {
+                    Some(Err(e)) => Some(format!("{} ({}): {}", case.name, who, e)),
+                    _ => None,
+                })
+        })
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_parse() {
+    let cases = parse(
+        "t",
+        "# first\nprogram: 1,0,0,0,99\nmemory: 2,0,0,0,99\n\ninput: 1, 2\noutput: 3\n",
+    );
+    assert_eq!(cases.len(), 2);
+    assert_eq!(cases[0].name, "t:2 first");
+    assert_eq!(cases[1].program, vec![1, 0, 0, 0, 99]);
+    assert_eq!(cases[1].input, vec![1, 2]);
+    assert_eq!(cases[1].memory, None);
+    assert_eq!(values("1, 0*3,2"), vec![1, 0, 0, 0, 2]);
+}
+
+#[test]
+fn test_day2() {
+    run_fixture("day2.txt");
+}
+
+#[test]
+fn test_day5() {
+    run_fixture("day5.txt");
+}
+
+#[test]
+fn test_day7() {
+    run_fixture("day7.txt");
+}
+
+#[test]
+fn test_day9() {
+    run_fixture("day9.txt");
+}
@@ -0,0 +1,192 @@
+use std::{borrow::BorrowMut, collections::VecDeque, marker::PhantomData};
+
+use super::{
+    cell::Cell,
+    io::Input,
+    memory::{Memory, Store},
+    trace::Record,
+    ExecutionError, IntcodeComputer,
+};
+
+/// what a running machine asks of the code driving it
+#[derive(Debug, PartialEq)]
+pub enum Yield<T = isize> {
+    Output(T),
+    /// the machine is stuck on an `in` until `Coroutine::respond` is called
+    NeedInput,
+    /// an access touched a watchpoint, the machine carries on when resumed
+    Watchpoint(Record<T>),
+}
+
+/// A machine run a little at a time: every `next` runs it until it outputs a
+/// value or needs one. The iterator ends once the machine halts, or after
+/// yielding the error it failed with. The machine is owned, or borrowed
+/// with `M = &mut IntcodeComputer<T>`, whose memory is `S`.
+pub struct Coroutine<T = isize, M = IntcodeComputer<T>, S = Memory<T>> {
+    com: M,
+    answers: VecDeque<T>,
+    done: bool,
+    cell: PhantomData<(T, S)>,
+}
+
+impl<T: Cell, M: BorrowMut<IntcodeComputer<T, S>>, S: Store<T>> Coroutine<T, M, S> {
+    pub fn new(com: M) -> Coroutine<T, M, S> {
+        Coroutine {
+            com,
+            answers: VecDeque::new(),
+            done: false,
+            cell: PhantomData,
+        }
+    }
+
+    /// queues a value for the next `in`, whether or not it was asked for yet
+    pub fn respond(&mut self, v: T) {
+        self.answers.push_back(v);
+    }
+
+    /// Sends `v` and runs until the next output, `None` if the machine halts
+    /// first. Asking for more input first is an error, watchpoints are
+    /// passed by.
+    pub fn call(&mut self, v: T) -> Result<Option<T>, ExecutionError> {
+        self.respond(v);
+        loop {
+            return match self.next() {
+                Some(Ok(Yield::Output(out))) => Ok(Some(out)),
+                Some(Ok(Yield::NeedInput)) => Err(ExecutionError::UnexpectedInput),
+                Some(Ok(Yield::Watchpoint(_))) => continue,
+                Some(Err(e)) => Err(e),
+                None => Ok(None),
+            };
+        }
+    }
+}
+
+impl<T: Cell, M: BorrowMut<IntcodeComputer<T, S>>, S: Store<T>> Iterator for Coroutine<T, M, S> {
+    type Item = Result<Yield<T>, ExecutionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let com = self.com.borrow_mut();
+        loop {
+            if let Some(hit) = com.take_watch_hit() {
+                return Some(Ok(Yield::Watchpoint(hit)));
+            }
+            let input = if com.wants_input() {
+                match self.answers.pop_front() {
+                    Some(v) => Some(v),
+                    None => return Some(Ok(Yield::NeedInput)),
+                }
+            } else {
+                None
+            };
+            match com.step(input) {
+                Ok((true, _)) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok((false, Some(v))) => return Some(Ok(Yield::Output(v))),
+                Ok((false, None)) => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// The outputs of a machine fed from an `Input`, produced as they are
+/// iterated over. Running out of input is an `ExecutionError::UnexpectedInput`,
+/// watchpoints are passed by.
+pub struct Outputs<T, I, S = Memory<T>> {
+    co: Coroutine<T, IntcodeComputer<T, S>, S>,
+    input: I,
+}
+
+impl<T: Cell, I: Input<T>, S: Store<T>> Outputs<T, I, S> {
+    pub fn new(com: IntcodeComputer<T, S>, input: I) -> Outputs<T, I, S> {
+        Outputs {
+            co: Coroutine::new(com),
+            input,
+        }
+    }
+}
+
+impl<T: Cell, I: Input<T>, S: Store<T>> Iterator for Outputs<T, I, S> {
+    type Item = Result<T, ExecutionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.co.next()? {
+                Ok(Yield::Output(v)) => return Some(Ok(v)),
+                Ok(Yield::Watchpoint(_)) => {}
+                Ok(Yield::NeedInput) => match self.input.read() {
+                    Some(v) => self.co.respond(v),
+                    None => {
+                        self.co.done = true;
+                        return Some(Err(ExecutionError::UnexpectedInput));
+                    }
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm};
+
+    // outputs the running sum of its inputs until it reads a 0
+    const SUMS: &str = "
+        loop:   in [x]
+                jz [x], #done
+                add [sum], [x], [sum]
+                out [sum]
+                jz #0, #loop
+        done:   hlt
+        x:      data 0
+        sum:    data 0
+    ";
+
+    fn sums() -> IntcodeComputer {
+        intcode::new(asm::assemble(SUMS).unwrap())
+    }
+
+    #[test]
+    fn test_outputs() {
+        let outputs = sums()
+            .outputs(VecDeque::from(vec![1, 2, 3, 0]))
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(outputs, Ok(vec![1, 3, 6]));
+
+        // lazily: nothing past the second output is read
+        let mut input = VecDeque::from(vec![1, 2, 3, 0]);
+        let taken = sums()
+            .outputs(|| input.pop_front())
+            .take(2)
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(taken, Ok(vec![1, 3]));
+        assert_eq!(input, vec![3, 0]);
+
+        let mut starved = sums().outputs(VecDeque::from(vec![5]));
+        assert_eq!(starved.next(), Some(Ok(5)));
+        assert_eq!(starved.next(), Some(Err(ExecutionError::UnexpectedInput)));
+        assert_eq!(starved.next(), None);
+    }
+
+    #[test]
+    fn test_coroutine() {
+        let mut co = sums().into_coroutine();
+        assert_eq!(co.next(), Some(Ok(Yield::NeedInput)));
+        assert_eq!(co.next(), Some(Ok(Yield::NeedInput)));
+        co.respond(4);
+        assert_eq!(co.next(), Some(Ok(Yield::Output(4))));
+        assert_eq!(co.call(6), Ok(Some(10)));
+        assert_eq!(co.call(0), Ok(None));
+        assert_eq!(co.next(), None);
+    }
+}
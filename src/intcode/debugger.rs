@@ -0,0 +1,463 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    io::{self, BufRead, Write},
+};
+
+use super::{
+    opcode::Opcode,
+    snapshot::Snapshot,
+    trace::{Access, Record},
+    IntcodeComputer,
+};
+
+const HELP: &str = "\
+commands:
+  s [n]            step n instructions (default 1)
+  rs [n]           step back n instructions (default 1)
+  rw <addr>        step back to just before the last write to addr
+  c                continue until a breakpoint, halt or missing input
+  b <addr>         break before executing the instruction at addr
+  bo <op>          break before any instruction with opcode (code or mnemonic)
+  d <addr>         delete address breakpoint
+  do <op>          delete opcode breakpoint
+  bl               list breakpoints
+  w <addr> [end]   pause after any instruction reading or writing addr..=end
+  ww <addr> [end]  same, but only on writes
+  wl               list watchpoints
+  wd               delete all watchpoints
+  x <addr> [n]     show n memory cells starting at addr (default 1)
+  poke <addr> <v>  write v to addr
+  r                show registers and the next instruction
+  l [n]            disassemble n instructions from the instruction pointer
+  i <v> [v ...]    queue input values
+  save <file>      save the machine and queued inputs (.json for JSON)
+  load <file>      restore a machine saved with save
+  q                quit";
+
+/// steps between checkpoints of the undo log, and how many checkpoints to
+/// keep, so the last 100k or so steps can be undone
+const REWIND_INTERVAL: usize = 1000;
+const REWIND_CHECKPOINTS: usize = 100;
+
+#[derive(Debug, PartialEq)]
+enum Stop {
+    Stepped,
+    Breakpoint,
+    Watchpoint(Record),
+    Halted,
+    NeedsInput,
+    Error(String),
+}
+
+enum CmdError {
+    Io(io::Error),
+    Usage(String),
+}
+
+impl From<io::Error> for CmdError {
+    fn from(e: io::Error) -> CmdError {
+        CmdError::Io(e)
+    }
+}
+
+impl From<String> for CmdError {
+    fn from(msg: String) -> CmdError {
+        CmdError::Usage(msg)
+    }
+}
+
+impl From<&str> for CmdError {
+    fn from(msg: &str) -> CmdError {
+        CmdError::Usage(msg.to_string())
+    }
+}
+
+pub struct Debugger {
+    com: IntcodeComputer,
+    inputs: VecDeque<isize>,
+    breakpoints: BTreeSet<usize>,
+    op_breakpoints: BTreeSet<isize>,
+    halted: bool,
+}
+
+impl Debugger {
+    pub fn new(mut com: IntcodeComputer, inputs: Vec<isize>) -> Debugger {
+        com.enable_history(REWIND_INTERVAL, REWIND_CHECKPOINTS);
+        Debugger {
+            com,
+            inputs: inputs.into(),
+            breakpoints: BTreeSet::new(),
+            op_breakpoints: BTreeSet::new(),
+            halted: false,
+        }
+    }
+
+    /// Reads commands from `input` until `q` or end of input, writing
+    /// everything the session prints to `out`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut out: W) -> io::Result<()> {
+        self.show_regs(&mut out)?;
+        write!(out, "> ")?;
+        out.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if let Some(&cmd) = words.first() {
+                if cmd == "q" {
+                    break;
+                }
+                match self.exec(cmd, &words[1..], &mut out) {
+                    Ok(()) => {}
+                    Err(CmdError::Usage(msg)) => writeln!(out, "error: {}", msg)?,
+                    Err(CmdError::Io(e)) => return Err(e),
+                }
+            }
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    fn exec<W: Write>(&mut self, cmd: &str, args: &[&str], out: &mut W) -> Result<(), CmdError> {
+        let nums = match args
+            .iter()
+            .map(|a| a.parse::<isize>())
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(n) => n,
+            Err(_) if ["bo", "do", "save", "load"].contains(&cmd) => vec![],
+            Err(e) => return Err(format!("cannot parse arguments: {}", e).into()),
+        };
+        let addr = |i: usize| -> Result<usize, String> {
+            match nums.get(i) {
+                Some(v) if *v >= 0 => Ok(*v as usize),
+                Some(v) => Err(format!("negative address {}", v)),
+                None => Err(format!("{} needs an address", cmd)),
+            }
+        };
+
+        match cmd {
+            "s" => {
+                let n = nums.first().copied().unwrap_or(1);
+                for _ in 0..n {
+                    let stop = self.step(out)?;
+                    if stop != Stop::Stepped {
+                        self.report(stop, out)?;
+                        break;
+                    }
+                }
+                self.show_regs(out)?;
+            }
+            "c" => {
+                let stop = loop {
+                    let stop = self.step(out)?;
+                    if stop != Stop::Stepped {
+                        break stop;
+                    }
+                    if self.at_breakpoint() {
+                        break Stop::Breakpoint;
+                    }
+                };
+                self.report(stop, out)?;
+                self.show_regs(out)?;
+            }
+            "rs" | "rw" => {
+                if self.com.history().is_none_or(|h| h.steps() == 0) {
+                    return Err("nothing recorded to rewind".into());
+                }
+                let rewound = if cmd == "rs" {
+                    let n = nums.first().copied().unwrap_or(1).max(0) as usize;
+                    self.com.rewind(n)
+                } else {
+                    let a = addr(0)?;
+                    self.com
+                        .rewind_to_write(a)
+                        .ok_or_else(|| format!("no recorded write to {}", a))?
+                };
+                // undone input instructions get to read the same values again
+                rewound
+                    .inputs
+                    .iter()
+                    .rev()
+                    .for_each(|v| self.inputs.push_front(*v));
+                if rewound.steps > 0 {
+                    self.halted = false;
+                }
+                writeln!(out, "rewound {} step(s)", rewound.steps)?;
+                self.show_regs(out)?;
+            }
+            "b" => {
+                self.breakpoints.insert(addr(0)?);
+            }
+            "d" => {
+                self.breakpoints.remove(&addr(0)?);
+            }
+            "bo" | "do" => {
+                let op = args
+                    .first()
+                    .and_then(|a| {
+                        Opcode::from_mnemonic(a)
+                            .or_else(|| a.parse::<isize>().ok().and_then(Opcode::from_code))
+                    })
+                    .ok_or_else(|| format!("{} needs an opcode", cmd))?;
+                if cmd == "bo" {
+                    self.op_breakpoints.insert(op.code());
+                } else {
+                    self.op_breakpoints.remove(&op.code());
+                }
+            }
+            "bl" => {
+                for b in self.breakpoints.iter() {
+                    writeln!(out, "address {}", b)?;
+                }
+                for b in self.op_breakpoints.iter() {
+                    let op = Opcode::from_code(*b).map_or("?", |o| o.mnemonic());
+                    writeln!(out, "opcode {} ({})", b, op)?;
+                }
+            }
+            "w" | "ww" => {
+                let start = addr(0)?;
+                let end = if nums.len() > 1 { addr(1)? } else { start };
+                let on = if cmd == "ww" {
+                    Some(Access::Write)
+                } else {
+                    None
+                };
+                let tracer = self.com.enable_tracing();
+                tracer.keep_records = false;
+                tracer.watch(start..=end, on);
+            }
+            "wl" => {
+                if let Some(t) = self.com.tracer() {
+                    for w in t.watchpoints() {
+                        let on = w.on.map_or("access".to_string(), |a| a.to_string());
+                        writeln!(out, "{} {}..={}", on, w.range.start(), w.range.end())?;
+                    }
+                }
+            }
+            "wd" => {
+                if let Some(t) = self.com.tracer.as_mut() {
+                    t.clear_watchpoints();
+                }
+            }
+            "x" => {
+                let start = addr(0)?;
+                let n = nums.get(1).copied().unwrap_or(1).max(0) as usize;
+                for a in start..start + n {
+                    writeln!(out, "{:>6}: {}", a, self.com.peek(a))?;
+                }
+            }
+            "poke" => {
+                let val = *nums.get(1).ok_or("poke needs a value")?;
+                self.com.set(addr(0)?, val);
+            }
+            "r" => self.show_regs(out)?,
+            "l" => {
+                let n = nums.first().copied().unwrap_or(5);
+                let mut pos = self.com.pos;
+                for _ in 0..n {
+                    match self.com.instruction_at(pos) {
+                        Some(ins) => {
+                            writeln!(out, "{:>6}: {}", ins.addr, ins)?;
+                            pos += ins.size();
+                        }
+                        None => {
+                            writeln!(out, "{:>6}: data {}", pos, self.com.peek(pos))?;
+                            pos += 1;
+                        }
+                    }
+                }
+            }
+            "i" => {
+                if nums.is_empty() {
+                    return Err("i needs at least one value".into());
+                }
+                self.inputs.extend(nums.iter());
+            }
+            "save" | "load" => {
+                let path = args
+                    .first()
+                    .ok_or_else(|| format!("{} needs a file", cmd))?;
+                if cmd == "save" {
+                    let mut snap = Snapshot::new(self.com.clone());
+                    snap.inputs = self.inputs.clone();
+                    snap.save(path).map_err(|e| e.to_string())?;
+                } else {
+                    let snap = Snapshot::load(path).map_err(|e| e.to_string())?;
+                    // a snapshot has no history or tracing, set them up as
+                    // `new` does and carry the watchpoints over
+                    let watchpoints = self
+                        .com
+                        .tracer()
+                        .map(|t| t.watchpoints().to_vec())
+                        .unwrap_or_default();
+                    self.com = snap.machine;
+                    self.com.enable_history(REWIND_INTERVAL, REWIND_CHECKPOINTS);
+                    if !watchpoints.is_empty() {
+                        let tracer = self.com.enable_tracing();
+                        tracer.keep_records = false;
+                        for w in watchpoints {
+                            tracer.watch(w.range, w.on);
+                        }
+                    }
+                    self.inputs = snap.inputs;
+                    self.halted = false;
+                    self.show_regs(out)?;
+                }
+            }
+            "h" | "help" => writeln!(out, "{}", HELP)?,
+            _ => return Err(format!("unknown command {}, try h", cmd).into()),
+        }
+        Ok(())
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.breakpoints.contains(&self.com.pos)
+            || self
+                .op_breakpoints
+                .contains(&(self.com.peek(self.com.pos) % 100))
+    }
+
+    fn step<W: Write>(&mut self, out: &mut W) -> io::Result<Stop> {
+        if self.halted {
+            return Ok(Stop::Halted);
+        }
+        let input = if self.com.wants_input() {
+            match self.inputs.pop_front() {
+                Some(v) => Some(v),
+                None => return Ok(Stop::NeedsInput),
+            }
+        } else {
+            None
+        };
+
+        match self.com.step(input) {
+            Ok((true, _)) => {
+                self.halted = true;
+                Ok(Stop::Halted)
+            }
+            Ok((false, output)) => {
+                if let Some(v) = output {
+                    writeln!(out, "output: {}", v)?;
+                }
+                match self.com.take_watch_hit() {
+                    Some(hit) => Ok(Stop::Watchpoint(hit)),
+                    None => Ok(Stop::Stepped),
+                }
+            }
+            Err(e) => Ok(Stop::Error(e.to_string())),
+        }
+    }
+
+    fn report<W: Write>(&self, stop: Stop, out: &mut W) -> io::Result<()> {
+        match stop {
+            Stop::Stepped => Ok(()),
+            Stop::Breakpoint => writeln!(out, "breakpoint at {}", self.com.pos),
+            Stop::Watchpoint(hit) => writeln!(out, "watchpoint: {}", hit),
+            Stop::Halted => writeln!(out, "halted"),
+            Stop::NeedsInput => writeln!(out, "waiting on input, queue values with i"),
+            Stop::Error(msg) => writeln!(out, "execution error: {}", msg),
+        }
+    }
+
+    fn show_regs<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let next = match self.com.current_instruction() {
+            Some(ins) => ins.to_string(),
+            None => format!("data {}", self.com.peek(self.com.pos)),
+        };
+        writeln!(
+            out,
+            "pos={} rb={} inputs={:?} | {}",
+            self.com.pos, self.com.relative_base, self.inputs, next
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode;
+
+    fn session(prog: Vec<isize>, script: &str) -> String {
+        let mut dbg = Debugger::new(intcode::new(prog), vec![]);
+        let mut out = vec![];
+        dbg.run(script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_waits_for_input() {
+        let out = session(vec![3, 0, 4, 0, 99], "c\ni 7\nc\n");
+        assert!(out.contains("waiting on input"));
+        assert!(out.contains("output: 7"));
+        assert!(out.contains("halted"));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // day 9 quine, stop on every output instruction
+        let prog = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let out = session(prog.clone(), "bo out\nc\nc\nr\n");
+        assert_eq!(out.matches("breakpoint at 2").count(), 2);
+        assert!(out.contains("output: 109"));
+        assert!(!out.contains("output: 1\n"));
+
+        let out = session(prog, "b 12\nc\nx 100\n");
+        assert!(out.contains("breakpoint at 12"));
+        assert!(out.contains("   100: 1"));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let out = session(vec![1, 0, 0, 5, 99, 0], "ww 5\nc\nc\n");
+        assert!(out.contains("watchpoint: write of 2 at 5 by instruction at 0"));
+        assert!(out.contains("halted"));
+    }
+
+    #[test]
+    fn test_poke_and_step() {
+        let out = session(vec![1, 0, 0, 0, 99], "poke 0 2\ns\nx 0\ns 5\n");
+        assert!(out.contains("     0: 4"));
+        assert!(out.contains("halted"));
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let out = session(
+            vec![3, 9, 1, 9, 9, 9, 4, 9, 99, 0],
+            "i 4\nc\nrs 2\nrw 8\nrw 9\nrs\nc\n",
+        );
+        assert_eq!(out.matches("output: 8").count(), 2);
+        assert!(out.contains("rewound 2 step(s)\npos=2 rb=0 inputs=[] | add [9], [9], [9]"));
+        assert!(out.contains("rewound 1 step(s)\npos=0 rb=0 inputs=[4] | in [9]"));
+        assert!(out.contains("error: no recorded write to 8"));
+        assert!(out.contains("error: nothing recorded to rewind"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("dbg-{}.json", std::process::id()));
+        let script = format!("i 5\ns\ni 6\nsave {0}\nc\nload {0}\nx 0\n", path.display());
+        let out = session(vec![3, 0, 4, 0, 3, 0, 4, 0, 99], &script);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(out.contains("output: 6"));
+        assert!(out.contains("pos=2 rb=0 inputs=[6]"));
+        assert!(out.contains("     0: 5"));
+    }
+
+    #[test]
+    fn test_load_keeps_history_and_watchpoints() {
+        let path = std::env::temp_dir().join(format!("dbg-rw-{}.json", std::process::id()));
+        let script = format!("i 5\nsave {0}\nww 0\nload {0}\nc\nrs\nwl\n", path.display());
+        let out = session(vec![3, 0, 4, 0, 99], &script);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(out.contains("watchpoint: write of 5 at 0 by instruction at 0"));
+        assert!(out.contains("rewound 1 step(s)\npos=0 rb=0 inputs=[5] | in [0]"));
+        assert!(out.contains("write 0..=0"));
+        assert!(!out.contains("nothing recorded to rewind"));
+    }
+}
@@ -0,0 +1,282 @@
+use std::convert::TryFrom;
+
+use super::{
+    cell::Cell,
+    memory::{Memory, Store},
+    modes::{DecodeError, Decoded},
+    opcode::{Opcode, ALL},
+    ExecutionError, IntcodeComputer,
+};
+
+/// what the machine does after an instruction ran
+#[derive(Debug, PartialEq)]
+pub enum Effect<T> {
+    /// carry on, at the jump target if the instruction set one
+    Next,
+    Output(T),
+    Halt,
+}
+
+pub type Handler<T, M = Memory<T>> = fn(&mut Exec<'_, T, M>) -> Result<Effect<T>, ExecutionError>;
+
+/// An instruction a `Dialect` can run.
+#[derive(Clone)]
+pub struct OpDef<T, M = Memory<T>> {
+    pub code: isize,
+    pub mnemonic: &'static str,
+    /// number of parameters following the opcode cell, at most 3
+    pub arity: usize,
+    /// whether the last parameter is a write target
+    pub writes: bool,
+    /// whether the instruction takes a value with `Exec::input`
+    pub input: bool,
+    pub exec: Handler<T, M>,
+}
+
+impl<T: Cell, M: Store<T>> OpDef<T, M> {
+    /// the definition of a standard instruction
+    pub fn standard(op: Opcode) -> OpDef<T, M> {
+        let exec: Handler<T, M> = match op {
+            Opcode::Add => |x| {
+                let (a, b) = (x.read(0)?, x.read(1)?);
+                let sum = x.arith(&a, &b, T::checked_add, T::wrapping_add)?;
+                x.write(2, sum)
+            },
+            Opcode::Mul => |x| {
+                let (a, b) = (x.read(0)?, x.read(1)?);
+                let product = x.arith(&a, &b, T::checked_mul, T::wrapping_mul)?;
+                x.write(2, product)
+            },
+            Opcode::In => |x| {
+                let v = x.input()?;
+                x.write(0, v)
+            },
+            Opcode::Out => |x| Ok(Effect::Output(x.read(0)?)),
+            Opcode::Jnz => |x| {
+                let (cond, to) = (x.read(0)?, x.read(1)?);
+                if cond != T::default() {
+                    x.jump(&to)?;
+                }
+                Ok(Effect::Next)
+            },
+            Opcode::Jz => |x| {
+                let (cond, to) = (x.read(0)?, x.read(1)?);
+                if cond == T::default() {
+                    x.jump(&to)?;
+                }
+                Ok(Effect::Next)
+            },
+            Opcode::Lt => |x| {
+                let (a, b) = (x.read(0)?, x.read(1)?);
+                x.write(2, T::from_isize(if a < b { 1 } else { 0 }))
+            },
+            Opcode::Eq => |x| {
+                let (a, b) = (x.read(0)?, x.read(1)?);
+                x.write(2, T::from_isize(if a == b { 1 } else { 0 }))
+            },
+            Opcode::Arb => |x| {
+                let by = x.read(0)?;
+                x.adjust_base(&by)?;
+                Ok(Effect::Next)
+            },
+            Opcode::Hlt => |_| Ok(Effect::Halt),
+        };
+        OpDef {
+            code: op.code(),
+            mnemonic: op.mnemonic(),
+            arity: op.arity(),
+            writes: op.writes(),
+            input: op == Opcode::In,
+            exec,
+        }
+    }
+}
+
+/// The instructions a machine understands, by opcode. Machines run the
+/// `standard` set unless given another one with `set_dialect`.
+#[derive(Clone)]
+pub struct Dialect<T = isize, M = Memory<T>> {
+    ops: Vec<Option<OpDef<T, M>>>,
+}
+
+impl<T: Cell, M: Store<T>> Dialect<T, M> {
+    /// no instructions at all, not even `hlt`
+    pub fn empty() -> Dialect<T, M> {
+        Dialect {
+            ops: vec![None; 100],
+        }
+    }
+
+    /// the complete instruction set, as of day 9
+    pub fn standard() -> Dialect<T, M> {
+        Dialect::only(&ALL)
+    }
+
+    /// the standard instructions in `ops`, and nothing else
+    pub fn only(ops: &[Opcode]) -> Dialect<T, M> {
+        ops.iter()
+            .fold(Dialect::empty(), |d, op| d.with(OpDef::standard(*op)))
+    }
+
+    /// Adds `def`, replacing the instruction with its opcode if there is
+    /// one. Panics if the opcode is not in 1..=99 or the parameters do not
+    /// fit an instruction.
+    pub fn with(mut self, def: OpDef<T, M>) -> Dialect<T, M> {
+        assert!(
+            (1..100).contains(&def.code),
+            "opcode {} out of range",
+            def.code
+        );
+        assert!(
+            def.arity <= 3,
+            "{} takes more than 3 parameters",
+            def.mnemonic
+        );
+        assert!(
+            !def.writes || def.arity > 0,
+            "{} writes without parameters",
+            def.mnemonic
+        );
+        let code = def.code as usize;
+        self.ops[code] = Some(def);
+        self
+    }
+
+    #[inline]
+    pub fn get(&self, code: isize) -> Option<&OpDef<T, M>> {
+        usize::try_from(code)
+            .ok()
+            .and_then(|c| self.ops.get(c))
+            .and_then(Option::as_ref)
+    }
+
+    /// splits an opcode cell according to the instruction it names
+    pub fn decode(&self, cell: isize) -> Result<Decoded, DecodeError> {
+        let op = self.get(cell % 100).ok_or(DecodeError::UnknownOpcode)?;
+        Decoded::split(cell, op.arity, op.writes)
+    }
+}
+
+/// The machine as seen by the instruction it is running. Errors name the
+/// instruction, and the machine moves past it afterwards unless it jumped.
+pub struct Exec<'a, T, M = Memory<T>> {
+    pub(super) com: &'a mut IntcodeComputer<T, M>,
+    pub(super) d: Decoded,
+    pub(super) input: Option<T>,
+    pub(super) jump: Option<usize>,
+}
+
+impl<'a, T: Cell, M: Store<T>> Exec<'a, T, M> {
+    /// the value of parameter `i`, counting from 0
+    pub fn read(&mut self, i: usize) -> Result<T, ExecutionError> {
+        self.com.get_param(i + 1, self.d.mode(i))
+    }
+
+    /// stores `v` where parameter `i` points
+    pub fn write(&mut self, i: usize, v: T) -> Result<Effect<T>, ExecutionError> {
+        let addr = self.com.get_param_write(i + 1, self.d.mode(i))?;
+        self.com.store(addr, v);
+        Ok(Effect::Next)
+    }
+
+    /// the value given to `step`, for instructions defined with `input`
+    pub fn input(&mut self) -> Result<T, ExecutionError> {
+        self.input
+            .take()
+            .ok_or(ExecutionError::MissingInput { addr: self.com.pos })
+    }
+
+    /// continues at address `to` instead of the next instruction
+    pub fn jump(&mut self, to: &T) -> Result<(), ExecutionError> {
+        self.jump = Some(self.com.address(self.com.offset(to)?)?);
+        Ok(())
+    }
+
+    pub fn adjust_base(&mut self, by: &T) -> Result<(), ExecutionError> {
+        self.com.relative_base = self.com.relative(by)?;
+        Ok(())
+    }
+
+    /// `a` and `b` combined by `checked` if the machine checks for overflow,
+    /// by `wrapping` otherwise
+    pub fn arith(
+        &self,
+        a: &T,
+        b: &T,
+        checked: fn(&T, &T) -> Option<T>,
+        wrapping: fn(&T, &T) -> T,
+    ) -> Result<T, ExecutionError> {
+        self.com.arith(a, b, checked, wrapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm, StopEvent};
+    use std::collections::VecDeque;
+
+    // `sub a, b, c`: c = a - b
+    fn sub() -> OpDef<isize> {
+        OpDef {
+            code: 10,
+            mnemonic: "sub",
+            arity: 3,
+            writes: true,
+            input: false,
+            exec: |x| {
+                let (a, b) = (x.read(0)?, x.read(1)?);
+                x.write(2, a - b)
+            },
+        }
+    }
+
+    #[test]
+    fn test_custom_op() {
+        // reads two values, outputs their difference
+        let prog = vec![3, 11, 3, 12, 10, 11, 12, 13, 4, 13, 99, 0, 0, 0];
+        let run = |com: &mut IntcodeComputer| {
+            let mut out = vec![];
+            com.run(&mut VecDeque::from(vec![50, 8]), &mut out)
+                .map(|_| out)
+        };
+
+        let mut com = intcode::new(prog.clone());
+        assert_eq!(
+            run(&mut com),
+            Err(ExecutionError::UnknownOpcode {
+                addr: 4,
+                instruction: 10
+            })
+        );
+
+        let mut com = intcode::new(prog);
+        com.set_dialect(Dialect::standard().with(sub()));
+        assert_eq!(run(&mut com), Ok(vec![42]));
+    }
+
+    #[test]
+    fn test_restricted() {
+        // day 2 example, which only needs add, mul and hlt
+        let day2 = Dialect::only(&[Opcode::Add, Opcode::Mul, Opcode::Hlt]);
+        let mut com = intcode::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        com.set_dialect(day2.clone());
+        assert_eq!(
+            com.run(&mut VecDeque::new(), &mut vec![]),
+            Ok(StopEvent::Finished)
+        );
+        assert_eq!(com.peek(0), 3500);
+
+        // no input there yet
+        let mut com = intcode::new(asm::assemble("in [0]\nhlt").unwrap());
+        com.set_dialect(day2);
+        assert!(!com.wants_input());
+        assert_eq!(
+            com.step(Some(1)),
+            Err(ExecutionError::UnknownOpcode {
+                addr: 0,
+                instruction: 3
+            })
+        );
+    }
+}
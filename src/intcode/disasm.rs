@@ -0,0 +1,294 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use super::modes::{Decoded, OpMode};
+use super::opcode::Opcode;
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub addr: usize,
+    pub op: Opcode,
+    pub params: Vec<(OpMode, isize)>,
+}
+
+impl Instruction {
+    pub fn size(&self) -> usize {
+        self.op.size()
+    }
+
+    /// the jump target if it is encoded as an immediate operand
+    pub fn static_target(&self) -> Option<usize> {
+        match self.op {
+            Opcode::Jnz | Opcode::Jz => match self.params[1] {
+                (OpMode::Immediate, t) if t >= 0 => Some(t as usize),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// addresses execution may continue at, as far as can be told without running
+    pub fn successors(&self) -> Vec<usize> {
+        let next = self.addr + self.size();
+        match self.op {
+            Opcode::Hlt => vec![],
+            Opcode::Jnz | Opcode::Jz => {
+                let cond = match self.params[0] {
+                    (OpMode::Immediate, v) => Some(v != 0),
+                    _ => None,
+                };
+                let jumps_on = self.op == Opcode::Jnz;
+                let mut succ = vec![];
+                if cond != Some(!jumps_on) {
+                    succ.extend(self.static_target());
+                }
+                if cond != Some(jumps_on) {
+                    succ.push(next);
+                }
+                succ
+            }
+            _ => vec![next],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.op.mnemonic())?;
+        for (i, (mode, v)) in self.params.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, Operand(*mode, *v))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Operand(pub OpMode, pub isize);
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand(OpMode::Position, v) => write!(f, "[{}]", v),
+            Operand(OpMode::Immediate, v) => write!(f, "#{}", v),
+            Operand(OpMode::Relative, v) if *v < 0 => write!(f, "[r{}]", v),
+            Operand(OpMode::Relative, v) => write!(f, "[r+{}]", v),
+        }
+    }
+}
+
+/// decodes the instruction at `addr`, if the cells there form a valid one
+pub fn decode(prog: &[isize], addr: usize) -> Option<Instruction> {
+    let cell = *prog.get(addr)?;
+    let d = Decoded::new(cell).ok()?;
+
+    let params = d
+        .modes()
+        .iter()
+        .enumerate()
+        .map(|(i, m)| prog.get(addr + 1 + i).map(|v| (*m, *v)))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Instruction {
+        addr,
+        op: d.op()?,
+        params,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Entry {
+    Code(Instruction),
+    Data(Vec<isize>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Line {
+    pub addr: usize,
+    pub entry: Entry,
+    /// addresses of instructions statically known to jump here
+    pub sources: Vec<usize>,
+}
+
+impl Line {
+    pub fn size(&self) -> usize {
+        match &self.entry {
+            Entry::Code(ins) => ins.size(),
+            Entry::Data(d) => d.len(),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (text, raw) = match &self.entry {
+            Entry::Code(ins) => {
+                let mut raw = vec![(ins.op.code() + mode_digits(&ins.params)).to_string()];
+                raw.extend(ins.params.iter().map(|(_, v)| v.to_string()));
+                (ins.to_string(), raw.join(","))
+            }
+            Entry::Data(d) => (
+                format!(
+                    "data {}",
+                    d.iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                "unreachable".to_string(),
+            ),
+        };
+
+        write!(f, "{:>6}: {:<40} ; {}", self.addr, text, raw)?;
+        if !self.sources.is_empty() {
+            write!(
+                f,
+                " <- {}",
+                self.sources
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn mode_digits(params: &[(OpMode, isize)]) -> isize {
+    params.iter().rev().fold(0, |acc, (m, _)| {
+        acc * 10
+            + match m {
+                OpMode::Position => 0,
+                OpMode::Immediate => 1,
+                OpMode::Relative => 2,
+            }
+    }) * 100
+}
+
+/// walks every path reachable from address 0 and returns the decoded
+/// instructions by their start address
+pub fn trace_reachable(prog: &[isize]) -> BTreeMap<usize, Instruction> {
+    let mut found = BTreeMap::new();
+    let mut todo = vec![0];
+    while let Some(addr) = todo.pop() {
+        if found.contains_key(&addr) {
+            continue;
+        }
+        if let Some(ins) = decode(prog, addr) {
+            todo.extend(ins.successors());
+            found.insert(addr, ins);
+        }
+    }
+    found
+}
+
+pub fn disassemble(prog: &[isize]) -> Vec<Line> {
+    let code = trace_reachable(prog);
+
+    let mut sources: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    code.values().for_each(|ins| {
+        if let Some(t) = ins.static_target() {
+            sources.entry(t).or_default().insert(ins.addr);
+        }
+    });
+    let sources_of = |addr: usize| -> Vec<usize> {
+        sources
+            .get(&addr)
+            .map(|s| s.iter().copied().collect())
+            .unwrap_or_default()
+    };
+
+    let mut lines = vec![];
+    let mut addr = 0;
+    while addr < prog.len() {
+        let line = match code.get(&addr) {
+            Some(ins) => Line {
+                addr,
+                entry: Entry::Code(ins.clone()),
+                sources: sources_of(addr),
+            },
+            None => {
+                let end = (addr + 1..prog.len())
+                    .take(DATA_PER_LINE - 1)
+                    .find(|a| code.contains_key(a))
+                    .unwrap_or_else(|| prog.len().min(addr + DATA_PER_LINE));
+                Line {
+                    addr,
+                    entry: Entry::Data(prog[addr..end].to_vec()),
+                    sources: sources_of(addr),
+                }
+            }
+        };
+        addr += line.size();
+        lines.push(line);
+    }
+    lines
+}
+
+/// `disassemble` for memory in segments, see `Store::segments`: the
+/// segment at address 0 as a program, any further out as data.
+pub fn disassemble_segments(segments: &[(usize, Vec<isize>)]) -> Vec<Line> {
+    let mut lines = vec![];
+    for (start, cells) in segments.iter() {
+        if *start == 0 {
+            lines.extend(disassemble(cells));
+            continue;
+        }
+        lines.extend(cells.chunks(DATA_PER_LINE).enumerate().map(|(i, d)| Line {
+            addr: start + i * DATA_PER_LINE,
+            entry: Entry::Data(d.to_vec()),
+            sources: vec![],
+        }));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operands() {
+        let prog = vec![21101, 5, 100, 3, 99];
+        let ins = decode(&prog, 0).unwrap();
+        assert_eq!(ins.op, Opcode::Add);
+        assert_eq!(ins.to_string(), "add #5, #100, [r+3]");
+
+        let ins = decode(&[204, -1], 0).unwrap();
+        assert_eq!(ins.to_string(), "out [r-1]");
+    }
+
+    #[test]
+    fn test_rejects_immediate_write() {
+        assert_eq!(decode(&[11101, 1, 1, 0], 0), None);
+        assert_eq!(decode(&[1, 1, 1], 0), None);
+        assert_eq!(decode(&[300, 1], 0), None);
+    }
+
+    #[test]
+    fn test_disassemble_marks_data() {
+        // day 2 example: code up to the halt, then three data cells
+        let prog = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let lines = disassemble(&prog);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[3].addr, 9);
+        assert_eq!(lines[3].entry, Entry::Data(vec![30, 40, 50]));
+        assert!(lines[3].to_string().contains("unreachable"));
+    }
+
+    #[test]
+    fn test_static_jumps() {
+        // jump over a data cell, conditional jump back on an unknown value
+        let prog = vec![1105, 1, 4, 7, 1006, 7, 0, 99];
+        let lines = disassemble(&prog);
+        let addrs = lines.iter().map(|l| l.addr).collect::<Vec<_>>();
+        assert_eq!(addrs, vec![0, 3, 4, 7]);
+        assert_eq!(lines[1].entry, Entry::Data(vec![7]));
+        assert_eq!(lines[0].sources, vec![4]);
+        assert_eq!(lines[2].sources, vec![0]);
+    }
+}
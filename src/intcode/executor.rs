@@ -0,0 +1,286 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use super::io::Output;
+
+struct Shared<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    receiving: bool,
+    waker: Option<Waker>,
+}
+
+/// An unbounded channel between tasks of one `Executor`.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(RefCell::new(Shared {
+        queue: VecDeque::new(),
+        senders: 1,
+        receiving: true,
+        waker: None,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub struct Sender<T> {
+    shared: Rc<RefCell<Shared<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// queues `v` without waiting, dropping it if the receiver is gone
+    pub fn send(&self, v: T) {
+        let mut s = self.shared.borrow_mut();
+        if s.receiving {
+            s.queue.push_back(v);
+            if let Some(w) = s.waker.take() {
+                w.wake();
+            }
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.borrow_mut().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// the last sender to go wakes the receiver, which then reads `None`
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut s = self.shared.borrow_mut();
+        s.senders -= 1;
+        if s.senders == 0 {
+            if let Some(w) = s.waker.take() {
+                w.wake();
+            }
+        }
+    }
+}
+
+impl<T> Output<T> for Sender<T> {
+    fn write(&mut self, v: T) {
+        self.send(v)
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Rc<RefCell<Shared<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// the next value, `None` once the channel is empty and every sender is gone
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { rx: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut s = self.shared.borrow_mut();
+        s.receiving = false;
+        s.queue.clear();
+    }
+}
+
+pub struct Recv<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut s = self.rx.shared.borrow_mut();
+        match s.queue.pop_front() {
+            Some(v) => Poll::Ready(Some(v)),
+            None if s.senders == 0 => Poll::Ready(None),
+            None => {
+                s.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// ids of the tasks to poll next
+type ReadyQueue = Arc<Mutex<VecDeque<usize>>>;
+
+struct TaskWaker {
+    id: usize,
+    ready: ReadyQueue,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        push(&self.ready, self.id);
+    }
+}
+
+fn push(ready: &ReadyQueue, id: usize) {
+    ready
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push_back(id);
+}
+
+/// Runs futures on the current thread, polling each one only after it was
+/// woken. Meant for machines waiting on each other through `channel`s.
+#[derive(Default)]
+pub struct Executor {
+    tasks: Vec<Option<Task>>,
+    ready: ReadyQueue,
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor::default()
+    }
+
+    /// queues `f` to be run by `run`, see `Handle` for its result
+    pub fn spawn<F: Future + 'static>(&mut self, f: F) -> Handle<F::Output> {
+        let result = Rc::new(RefCell::new(None));
+        let slot = result.clone();
+        self.tasks.push(Some(Box::pin(async move {
+            let out = f.await;
+            *slot.borrow_mut() = Some(out);
+        })));
+        push(&self.ready, self.tasks.len() - 1);
+        Handle { result }
+    }
+
+    /// Polls tasks until none of them can make progress, returning how many
+    /// did not finish: those are waiting on a value nobody is going to send.
+    pub fn run(&mut self) -> usize {
+        loop {
+            let next = self
+                .ready
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .pop_front();
+            let id = match next {
+                Some(id) => id,
+                None => break,
+            };
+            // a task can be woken again after it finished
+            let task = match self.tasks[id].as_mut() {
+                Some(task) => task,
+                None => continue,
+            };
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                ready: self.ready.clone(),
+            }));
+            if task
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                self.tasks[id] = None;
+            }
+        }
+        self.tasks.iter().filter(|t| t.is_some()).count()
+    }
+}
+
+/// The result of a spawned task.
+pub struct Handle<T> {
+    result: Rc<RefCell<Option<T>>>,
+}
+
+impl<T> Handle<T> {
+    /// the result once the task finished, taken out of the handle
+    pub fn take(&self) -> Option<T> {
+        self.result.borrow_mut().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm, StopEvent};
+
+    // adds one to every value it reads
+    const INC: &str = "
+        loop:   in [x]
+                add [x], #1, [x]
+                out [x]
+                jz #0, #loop
+        x:      data 0
+    ";
+
+    #[test]
+    fn test_channel() {
+        let mut exec = Executor::new();
+        let (tx, mut rx) = channel();
+        let read = exec.spawn(async move {
+            let mut seen = vec![];
+            while let Some(v) = rx.recv().await {
+                seen.push(v);
+            }
+            seen
+        });
+
+        tx.send(1);
+        let later = tx.clone();
+        assert_eq!(exec.run(), 1);
+        tx.send(2);
+        drop(tx);
+        later.send(3);
+        assert_eq!(exec.run(), 1);
+        assert_eq!(read.take(), None);
+
+        drop(later);
+        assert_eq!(exec.run(), 0);
+        assert_eq!(read.take(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_machines() {
+        // three incrementers in a row, fed from here
+        let inc = intcode::new(asm::assemble(INC).unwrap());
+        let mut exec = Executor::new();
+        let (input, mut rx) = channel();
+        let mut machines = vec![];
+        for _ in 0..3 {
+            let (mut tx, next) = channel();
+            let mut com = inc.clone();
+            machines.push(exec.spawn(async move { com.run_async(&mut rx, &mut tx).await }));
+            rx = next;
+        }
+        let mut out = rx;
+        let collected = exec.spawn(async move {
+            let mut seen = vec![];
+            while let Some(v) = out.recv().await {
+                seen.push(v);
+            }
+            seen
+        });
+
+        input.send(10);
+        input.send(20);
+        assert_eq!(exec.run(), 4);
+        drop(input);
+        assert_eq!(exec.run(), 0);
+        assert_eq!(collected.take(), Some(vec![13, 23]));
+        for m in machines {
+            assert_eq!(m.take(), Some(Ok(StopEvent::WaitingOnInput)));
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use super::{
+    memory::Store,
+    opcode::{Opcode, ALL},
+    reference::{self, Outcome, Stop},
+};
+
+/// cells of data after the generated code, for the code to work on
+const SCRATCH: usize = 16;
+
+/// A xorshift64* generator, so that a seed is all it takes to repeat a case.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // the state must not be 0
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// a number in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// a number in `lo..hi`
+    pub fn range(&mut self, lo: isize, hi: isize) -> isize {
+        lo + self.below((hi - lo) as usize) as isize
+    }
+}
+
+/// A generated program together with the input it is given.
+pub struct Case {
+    pub seed: u64,
+    pub program: Vec<isize>,
+    pub inputs: Vec<isize>,
+}
+
+impl Case {
+    /// Generates about `len` cells of valid instructions, followed by some
+    /// data. Operands mostly point into the program, so it works on itself
+    /// as much as on the data, and jumps mostly land on an instruction.
+    pub fn generate(seed: u64, len: usize) -> Case {
+        let mut rng = Rng::new(seed);
+        let extent = (len + SCRATCH) as isize;
+        let mut program = vec![];
+        let mut starts = vec![];
+        let mut targets = vec![];
+
+        while program.len() < len {
+            let op = loop {
+                let op = ALL[rng.below(ALL.len())];
+                if op != Opcode::Hlt || rng.below(8) == 0 {
+                    break op;
+                }
+            };
+            let modes = (0..op.arity())
+                .map(|i| {
+                    if op.writes() && i == op.arity() - 1 {
+                        [0, 2][rng.below(2)]
+                    } else {
+                        rng.below(3)
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            starts.push(program.len());
+            program.push(
+                modes.iter().rev().fold(0, |acc, m| acc * 10 + *m as isize) * 100 + op.code(),
+            );
+            for (i, mode) in modes.iter().enumerate() {
+                let jumps = matches!(op, Opcode::Jnz | Opcode::Jz) && i == 1;
+                if jumps && *mode == 1 {
+                    targets.push(program.len());
+                }
+                program.push(match mode {
+                    0 | 2 => rng.range(0, extent),
+                    _ if op == Opcode::Arb => rng.range(-4, 8),
+                    // now and then a value big enough to overflow
+                    _ if rng.below(10) == 0 => (rng.next() >> rng.below(64)) as isize,
+                    _ => rng.range(-10, 100),
+                });
+            }
+        }
+        for t in targets {
+            if rng.below(10) > 0 {
+                program[t] = starts[rng.below(starts.len())] as isize;
+            }
+        }
+        program.extend((0..SCRATCH).map(|_| rng.range(-10, 100)));
+
+        let inputs = (0..rng.below(8)).map(|_| rng.range(-10, 100)).collect();
+        Case {
+            seed,
+            program,
+            inputs,
+        }
+    }
+}
+
+/// where `IntcodeComputer` and the reference interpreter disagree
+#[derive(Debug, PartialEq)]
+pub enum Divergence {
+    Outputs {
+        expected: Vec<isize>,
+        actual: Vec<isize>,
+    },
+    /// why the machines stopped, and after how many instructions
+    Stop {
+        expected: (Stop, usize),
+        actual: (Stop, usize),
+    },
+    Memory {
+        addr: usize,
+        expected: isize,
+        actual: isize,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Outputs { expected, actual } => {
+                write!(f, "outputs {:?}, expected {:?}", actual, expected)
+            }
+            Divergence::Stop { expected, actual } => write!(
+                f,
+                "{:?} after {} steps, expected {:?} after {}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Divergence::Memory {
+                addr,
+                expected,
+                actual,
+            } => write!(f, "{} at address {}, expected {}", actual, addr, expected),
+        }
+    }
+}
+
+/// Runs `prog` for at most `max_steps` instructions on `IntcodeComputer`
+/// and on the reference interpreter, comparing outputs, how they stopped
+/// and memory, see `compare_memory`.
+pub fn check(prog: &[isize], inputs: &[isize], max_steps: usize) -> Result<Outcome, Divergence> {
+    let expected = reference::run(prog, inputs, max_steps);
+
+    let mut com = super::new(prog.to_vec());
+    let mut inputs = inputs.iter().copied().collect::<VecDeque<_>>();
+    let mut outputs = vec![];
+    let mut steps = 0;
+    let stop = loop {
+        if steps == max_steps {
+            break Stop::StepLimit;
+        }
+        let input = if com.wants_input() {
+            inputs.pop_front()
+        } else {
+            None
+        };
+        match com.step(input) {
+            Ok((true, _)) => break Stop::Halted,
+            Ok((false, out)) => {
+                outputs.extend(out);
+                steps += 1;
+            }
+            Err(super::ExecutionError::MissingInput { .. }) => break Stop::NeedsInput,
+            Err(_) => break Stop::Fault { addr: com.pos },
+        }
+    };
+
+    if outputs != expected.outputs {
+        return Err(Divergence::Outputs {
+            expected: expected.outputs,
+            actual: outputs,
+        });
+    }
+    if (stop, steps) != (expected.stop, expected.steps) {
+        return Err(Divergence::Stop {
+            expected: (expected.stop, expected.steps),
+            actual: (stop, steps),
+        });
+    }
+    compare_memory(&expected.memory, &com.mem.segments())?;
+    Ok(expected)
+}
+
+/// Compares every address the reference loaded or wrote and every cell
+/// allocated on the other side, given as `Memory::segments`. A cell missing
+/// on one side counts as 0, like it reads.
+fn compare_memory(
+    expected: &HashMap<usize, isize>,
+    segments: &[(usize, Vec<isize>)],
+) -> Result<(), Divergence> {
+    let actual = segments
+        .iter()
+        .flat_map(|(start, cells)| cells.iter().enumerate().map(move |(i, v)| (start + i, *v)))
+        .collect::<HashMap<_, _>>();
+    let mut addrs = expected
+        .keys()
+        .chain(actual.keys())
+        .copied()
+        .collect::<Vec<_>>();
+    addrs.sort_unstable();
+    addrs.dedup();
+    for addr in addrs {
+        let want = expected.get(&addr).copied().unwrap_or(0);
+        let got = actual.get(&addr).copied().unwrap_or(0);
+        if want != got {
+            return Err(Divergence::Memory {
+                addr,
+                expected: want,
+                actual: got,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_generate() {
+        let case = Case::generate(7, 40);
+        assert_eq!(case.program, Case::generate(7, 40).program);
+        assert!(case.program.len() >= 40 + SCRATCH);
+        // the code part decodes from start to end
+        let mut addr = 0;
+        while addr < 40 {
+            let op = Opcode::from_code(case.program[addr] % 100).unwrap();
+            addr += op.size();
+        }
+    }
+
+    #[test]
+    fn test_no_divergence() {
+        let mut rng = Rng::new(2019);
+        let mut stops = HashSet::new();
+        for _ in 0..500 {
+            let case = Case::generate(rng.next(), 40);
+            match check(&case.program, &case.inputs, 2000) {
+                Ok(outcome) => stops.insert(std::mem::discriminant(&outcome.stop)),
+                Err(d) => panic!("case {} diverges: {}", case.seed, d),
+            };
+        }
+        // the cases got to stop in every way there is
+        assert_eq!(stops.len(), 4);
+    }
+
+    #[test]
+    fn test_compare_memory() {
+        let expected = vec![(0, 1), (2, 5)].into_iter().collect::<HashMap<_, _>>();
+        assert_eq!(compare_memory(&expected, &[(0, vec![1, 0, 5, 0])]), Ok(()));
+        // a write the reference never made still counts
+        assert_eq!(
+            compare_memory(&expected, &[(0, vec![1, 0, 5]), (1024, vec![7])]),
+            Err(Divergence::Memory {
+                addr: 1024,
+                expected: 0,
+                actual: 7
+            })
+        );
+        assert_eq!(
+            compare_memory(&expected, &[(0, vec![1])]),
+            Err(Divergence::Memory {
+                addr: 2,
+                expected: 5,
+                actual: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+
+use super::{
+    cell::Cell,
+    memory::{Memory, Store},
+};
+
+/// registers before a step, where its writes start in the chunk's undo log
+/// and the input it consumed, if any
+#[derive(Clone)]
+struct Step<T> {
+    pos: usize,
+    relative_base: isize,
+    writes: usize,
+    input: Option<T>,
+}
+
+/// A run of consecutive steps and the machine state before the first one.
+/// Memory is shared copy-on-write with the live machine, so a checkpoint only
+/// costs the pages written after it.
+#[derive(Clone)]
+struct Chunk<T, M> {
+    mem: M,
+    pos: usize,
+    relative_base: isize,
+    steps: Vec<Step<T>>,
+    /// `(address, previous value)` of every write, in execution order
+    writes: Vec<(usize, T)>,
+}
+
+/// what a rewind undid
+#[derive(Debug, PartialEq)]
+pub struct Rewound<T = isize> {
+    pub steps: usize,
+    /// values consumed by the undone `in` instructions, in the order read
+    pub inputs: Vec<T>,
+}
+
+/// Undo log of executed steps. Every `interval` steps a checkpoint is taken
+/// and only the last `max_chunks` checkpoints are kept, which bounds how far
+/// back execution can be rewound.
+#[derive(Clone)]
+pub struct History<T = isize, M = Memory<T>> {
+    chunks: VecDeque<Chunk<T, M>>,
+    interval: usize,
+    max_chunks: usize,
+    /// set between `begin` and `commit`/`discard`, writes outside of a step
+    /// (e.g. poking memory from the debugger) are not undone
+    open: bool,
+}
+
+impl<T: Cell, M: Store<T>> History<T, M> {
+    pub fn new(interval: usize, max_chunks: usize) -> History<T, M> {
+        History {
+            chunks: VecDeque::new(),
+            interval: interval.max(1),
+            max_chunks: max_chunks.max(1),
+            open: false,
+        }
+    }
+
+    /// how many steps can currently be undone
+    pub fn steps(&self) -> usize {
+        self.chunks.iter().map(|c| c.steps.len()).sum()
+    }
+
+    pub(super) fn begin(&mut self, mem: &M, pos: usize, relative_base: isize) {
+        if self
+            .chunks
+            .back()
+            .is_none_or(|c| c.steps.len() >= self.interval)
+        {
+            self.chunks.push_back(Chunk {
+                mem: mem.clone(),
+                pos,
+                relative_base,
+                steps: vec![],
+                writes: vec![],
+            });
+            if self.chunks.len() > self.max_chunks {
+                self.chunks.pop_front();
+            }
+        }
+
+        let chunk = self.chunks.back_mut().expect("a chunk was just pushed");
+        chunk.steps.push(Step {
+            pos,
+            relative_base,
+            writes: chunk.writes.len(),
+            input: None,
+        });
+        self.open = true;
+    }
+
+    pub(super) fn write(&mut self, addr: usize, old: T) {
+        if self.open {
+            if let Some(chunk) = self.chunks.back_mut() {
+                chunk.writes.push((addr, old));
+            }
+        }
+    }
+
+    /// keeps the step opened by `begin`, noting the input it consumed
+    pub(super) fn commit(&mut self, input: Option<T>) {
+        if let Some(step) = self.chunks.back_mut().and_then(|c| c.steps.last_mut()) {
+            step.input = input;
+        }
+        self.open = false;
+    }
+
+    /// drops the step opened by `begin`, for steps that halted or failed
+    /// without changing the machine
+    pub(super) fn discard(&mut self) {
+        if let Some(chunk) = self.chunks.back_mut() {
+            if let Some(step) = chunk.steps.pop() {
+                chunk.writes.truncate(step.writes);
+            }
+            if chunk.steps.is_empty() {
+                self.chunks.pop_back();
+            }
+        }
+        self.open = false;
+    }
+
+    /// Undoes the last `n` steps, or as many as are recorded. Whole chunks
+    /// are dropped by restoring their checkpoint instead of undoing each write.
+    pub(super) fn rewind(
+        &mut self,
+        n: usize,
+        mem: &mut M,
+        pos: &mut usize,
+        relative_base: &mut isize,
+    ) -> Rewound<T> {
+        let mut remaining = n.min(self.steps());
+        let mut rewound = Rewound {
+            steps: remaining,
+            inputs: vec![],
+        };
+
+        while remaining > 0 {
+            let chunk = self.chunks.back_mut().expect("enough steps are recorded");
+            if chunk.steps.len() <= remaining {
+                let chunk = self.chunks.pop_back().expect("checked above");
+                remaining -= chunk.steps.len();
+                rewound
+                    .inputs
+                    .extend(chunk.steps.into_iter().rev().filter_map(|s| s.input));
+                *mem = chunk.mem;
+                *pos = chunk.pos;
+                *relative_base = chunk.relative_base;
+                continue;
+            }
+
+            for _ in 0..remaining {
+                let step = chunk.steps.pop().expect("checked above");
+                for (addr, old) in chunk.writes.drain(step.writes..).rev() {
+                    mem.set(addr, old);
+                }
+                rewound.inputs.extend(step.input);
+                *pos = step.pos;
+                *relative_base = step.relative_base;
+            }
+            remaining = 0;
+        }
+
+        rewound.inputs.reverse();
+        rewound
+    }
+
+    /// how many steps ago `addr` was last written, counting the writing step
+    pub(super) fn since_write(&self, addr: usize) -> Option<usize> {
+        let mut ago = 0;
+        for chunk in self.chunks.iter().rev() {
+            let mut end = chunk.writes.len();
+            for step in chunk.steps.iter().rev() {
+                ago += 1;
+                if chunk.writes[step.writes..end]
+                    .iter()
+                    .any(|(a, _)| *a == addr)
+                {
+                    return Some(ago);
+                }
+                end = step.writes;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm, IntcodeComputer};
+
+    // sums its inputs into [sum] until it reads a 0, then outputs the sum
+    const SUM: &str = "
+        loop:   in [x]
+                jz [x], #done
+                add [sum], [x], [sum]
+                jz #0, #loop
+        done:   out [sum]
+                hlt
+        x:      data 0
+        sum:    data 0
+    ";
+
+    fn machine() -> IntcodeComputer {
+        let mut com = intcode::new(asm::assemble(SUM).unwrap());
+        com.enable_history(4, 100);
+        com
+    }
+
+    fn state(com: &IntcodeComputer) -> (usize, isize, Vec<isize>) {
+        (
+            com.pos,
+            com.relative_base,
+            (0..20).map(|a| com.peek(a)).collect(),
+        )
+    }
+
+    fn run(com: &mut IntcodeComputer, inputs: &[isize]) -> Vec<isize> {
+        let mut out = vec![];
+        com.run(&mut VecDeque::from(inputs.to_vec()), &mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_rewind_steps() {
+        let mut com = machine();
+        run(&mut com, &[1, 2]);
+        let before = state(&com);
+        let taken = com.history().unwrap().steps();
+
+        run(&mut com, &[3, 4, 0]);
+        let rewound = com.rewind(com.history().unwrap().steps() - taken);
+        assert_eq!(rewound.inputs, vec![3, 4, 0]);
+        assert_eq!(state(&com), before);
+        assert_eq!(run(&mut com, &[10, 0]), vec![13]);
+
+        // rewinding further than recorded stops at the start
+        let rewound = com.rewind(1000);
+        assert_eq!(rewound.inputs, vec![1, 2, 10, 0]);
+        assert_eq!(state(&com), state(&machine()));
+    }
+
+    #[test]
+    fn test_rewind_to_write() {
+        let mut com = machine();
+        assert_eq!(run(&mut com, &[5, 6, 0]), vec![11]);
+
+        // back to the add that stored 5 + 6
+        let rewound = com.rewind_to_write(16).unwrap();
+        assert_eq!(rewound.inputs, vec![0]);
+        assert_eq!((com.pos, com.peek(16)), (5, 5));
+        assert_eq!(com.rewind_to_write(19), None);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut com = intcode::new(asm::assemble(SUM).unwrap());
+        com.enable_history(4, 2);
+        run(&mut com, &[1; 10]);
+        assert_eq!(com.history().unwrap().steps(), 8);
+
+        let rewound = com.rewind(10);
+        assert_eq!(rewound.steps, 8);
+        assert_eq!(com.peek(16), 8);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{collections::VecDeque, sync::mpsc};
+
+/// Where a machine takes its input values from. `None` means no value is
+/// available (yet), which pauses `IntcodeComputer::run`.
+pub trait Input<T = isize> {
+    fn read(&mut self) -> Option<T>;
+}
+
+/// Where a machine sends its output values.
+pub trait Output<T = isize> {
+    fn write(&mut self, v: T);
+}
+
+impl<T> Input<T> for VecDeque<T> {
+    fn read(&mut self) -> Option<T> {
+        self.pop_front()
+    }
+}
+
+impl<T> Output<T> for VecDeque<T> {
+    fn write(&mut self, v: T) {
+        self.push_back(v)
+    }
+}
+
+impl<T> Output<T> for Vec<T> {
+    fn write(&mut self, v: T) {
+        self.push(v)
+    }
+}
+
+/// Blocks until a value arrives, starving only once every sender is gone.
+impl<T> Input<T> for mpsc::Receiver<T> {
+    fn read(&mut self) -> Option<T> {
+        self.recv().ok()
+    }
+}
+
+/// Values sent after the receiver hung up are dropped.
+impl<T> Output<T> for mpsc::Sender<T> {
+    fn write(&mut self, v: T) {
+        let _ = self.send(v);
+    }
+}
+
+impl<T, F: FnMut() -> Option<T>> Input<T> for F {
+    fn read(&mut self) -> Option<T> {
+        self()
+    }
+}
+
+impl<T, F: FnMut(T)> Output<T> for F {
+    fn write(&mut self, v: T) {
+        self(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, StopEvent};
+    use std::thread;
+
+    // day 5 example: outputs 999, 1000 or 1001 for input below, equal to or above 8
+    const CMP_8: [isize; 47] = [
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
+        1105, 1, 46, 98, 99,
+    ];
+
+    #[test]
+    fn test_queue_and_collector() {
+        let mut com = intcode::new(CMP_8.to_vec());
+        let mut out = vec![];
+        let event = com.run(&mut VecDeque::from(vec![8]), &mut out).unwrap();
+        assert_eq!(event, StopEvent::Finished);
+        assert_eq!(out, vec![1000]);
+    }
+
+    #[test]
+    fn test_starvation_resumes() {
+        let mut com = intcode::new(vec![3, 0, 4, 0, 3, 0, 4, 0, 99]);
+        let mut input = VecDeque::from(vec![1]);
+        let mut out = vec![];
+        assert_eq!(
+            com.run(&mut input, &mut out).unwrap(),
+            StopEvent::WaitingOnInput
+        );
+        input.push_back(2);
+        assert_eq!(com.run(&mut input, &mut out).unwrap(), StopEvent::Finished);
+        assert_eq!(out, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut com = intcode::new(CMP_8.to_vec());
+        let mut seen = vec![];
+        com.run(&mut || Some(3), &mut |v| seen.push(v)).unwrap();
+        assert_eq!(seen, vec![999]);
+    }
+
+    #[test]
+    fn test_channels() {
+        let (in_tx, mut in_rx) = mpsc::channel();
+        let (mut out_tx, out_rx) = mpsc::channel();
+        let mut com = intcode::new(CMP_8.to_vec());
+        let handle = thread::spawn(move || com.run(&mut in_rx, &mut out_tx));
+
+        in_tx.send(9).unwrap();
+        assert_eq!(out_rx.recv().unwrap(), 1001);
+        assert_eq!(handle.join().unwrap().unwrap(), StopEvent::Finished);
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use super::{
+    cell::Cell,
+    memory::{Memory, Store},
+};
+
+/// how many steps pass between two looks at the clock
+pub(super) const CLOCK_EVERY: u64 = 1024;
+
+/// What a machine may do before it is stopped, see `IntcodeComputer::set_limits`.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// instructions to execute at most
+    pub max_steps: Option<u64>,
+    /// wall-clock time allowed, counted from when the limits were set or
+    /// the machine was cloned, so every clone gets its own
+    pub timeout: Option<Duration>,
+    /// stop once the machine is back in a state it was in before without
+    /// having read input since, as it would then repeat itself forever
+    pub detect_loops: bool,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_steps.is_none() && self.timeout.is_none() && !self.detect_loops
+    }
+}
+
+/// the limit a machine ran into
+#[derive(Clone, Debug, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Timeout(Duration),
+    /// the instruction pointer and the number of steps the cycle takes
+    Loop {
+        pos: usize,
+        period: u64,
+    },
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps(n) => write!(f, "step budget of {} exhausted", n),
+            Limit::Timeout(d) => write!(f, "timed out after {:?}", d),
+            Limit::Loop { pos, period } => {
+                write!(f, "endless loop of {} step(s) at {}", period, pos)
+            }
+        }
+    }
+}
+
+/// a state kept for comparison by the loop detector
+#[derive(Clone)]
+struct Saved<M> {
+    hash: u64,
+    pos: usize,
+    relative_base: isize,
+    mem: M,
+}
+
+/// Enforces `Limits` on a running machine.
+///
+/// Loops are found with Brent's cycle detection: a state is saved at
+/// doubling intervals and every following state is compared to it. States
+/// are told apart by a hash of memory kept up to date on every write, only a
+/// matching hash costs a full comparison.
+pub(super) struct Guard<T, M = Memory<T>> {
+    limits: Limits,
+    start: Instant,
+    steps: u64,
+    /// sum of a hash per non-zero cell, so a write only changes two terms
+    mem_hash: u64,
+    saved: Option<Saved<M>>,
+    power: u64,
+    lambda: u64,
+    looping: Option<Limit>,
+    cell: PhantomData<T>,
+}
+
+/// a clone gets the whole timeout again, like a freshly limited machine
+impl<T, M: Clone> Clone for Guard<T, M> {
+    fn clone(&self) -> Self {
+        Guard {
+            limits: self.limits.clone(),
+            start: Instant::now(),
+            steps: self.steps,
+            mem_hash: self.mem_hash,
+            saved: self.saved.clone(),
+            power: self.power,
+            lambda: self.lambda,
+            looping: self.looping.clone(),
+            cell: PhantomData,
+        }
+    }
+}
+
+impl<T: Cell, M: Store<T>> Guard<T, M> {
+    pub(super) fn new(limits: Limits, mem: &M) -> Guard<T, M> {
+        let mut g = Guard {
+            limits,
+            start: Instant::now(),
+            steps: 0,
+            mem_hash: 0,
+            saved: None,
+            power: 1,
+            lambda: 0,
+            looping: None,
+            cell: PhantomData,
+        };
+        g.reset_loops(mem);
+        g
+    }
+
+    pub(super) fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// fails if the machine must not execute another step
+    pub(super) fn check(&self) -> Result<(), Limit> {
+        if let Some(l) = &self.looping {
+            return Err(l.clone());
+        }
+        if let Some(max) = self.limits.max_steps {
+            if self.steps >= max {
+                return Err(Limit::Steps(max));
+            }
+        }
+        if let Some(timeout) = self.limits.timeout {
+            if self.steps.is_multiple_of(CLOCK_EVERY) && self.start.elapsed() >= timeout {
+                return Err(Limit::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn write(&mut self, addr: usize, old: &T, new: &T) {
+        if self.limits.detect_loops {
+            self.mem_hash = self
+                .mem_hash
+                .wrapping_sub(cell_hash(addr, old))
+                .wrapping_add(cell_hash(addr, new));
+        }
+    }
+
+    /// accounts for a step that did not halt, `read` telling whether it
+    /// consumed input
+    pub(super) fn stepped(&mut self, mem: &M, pos: usize, relative_base: isize, read: bool) {
+        self.steps += 1;
+        if !self.limits.detect_loops {
+            return;
+        }
+
+        let hash = self.mem_hash ^ state_hash(pos, relative_base);
+        if read {
+            self.save(hash, mem, pos, relative_base);
+            self.power = 1;
+            self.lambda = 0;
+            return;
+        }
+
+        self.lambda += 1;
+        let repeated = self.saved.as_ref().is_some_and(|s| {
+            s.hash == hash
+                && s.pos == pos
+                && s.relative_base == relative_base
+                && s.mem.same_cells(mem)
+        });
+        if repeated {
+            self.looping = Some(Limit::Loop {
+                pos,
+                period: self.lambda,
+            });
+        } else if self.lambda == self.power {
+            self.save(hash, mem, pos, relative_base);
+            self.power *= 2;
+            self.lambda = 0;
+        }
+    }
+
+    /// starts loop detection over, for when memory changed behind its back
+    pub(super) fn reset_loops(&mut self, mem: &M) {
+        self.saved = None;
+        self.looping = None;
+        self.power = 1;
+        self.lambda = 0;
+        if self.limits.detect_loops {
+            self.mem_hash = mem.segments().iter().fold(0u64, |acc, (start, cells)| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .fold(acc, |acc, (i, v)| acc.wrapping_add(cell_hash(start + i, v)))
+            });
+        }
+    }
+
+    fn save(&mut self, hash: u64, mem: &M, pos: usize, relative_base: isize) {
+        self.saved = Some(Saved {
+            hash,
+            pos,
+            relative_base,
+            mem: mem.clone(),
+        });
+    }
+}
+
+/// zero cells hash to 0 whether their page is allocated or not
+fn cell_hash<T: Cell>(addr: usize, v: &T) -> u64 {
+    if *v == T::default() {
+        return 0;
+    }
+    let mut h = DefaultHasher::new();
+    (addr, v).hash(&mut h);
+    h.finish()
+}
+
+fn state_hash(pos: usize, relative_base: isize) -> u64 {
+    let mut h = DefaultHasher::new();
+    (pos, relative_base).hash(&mut h);
+    h.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm, ExecutionError, StopEvent};
+    use std::collections::VecDeque;
+
+    fn limited(src: &str, limits: Limits) -> intcode::IntcodeComputer {
+        let mut com = intcode::new(asm::assemble(src).unwrap());
+        com.set_limits(limits);
+        com
+    }
+
+    // counts up forever, never repeating a state
+    const COUNT: &str = "
+        loop:   add [n], #1, [n]
+                jz #0, #loop
+        n:      data 0
+    ";
+
+    #[test]
+    fn test_step_budget() {
+        let limits = Limits {
+            max_steps: Some(10),
+            detect_loops: true,
+            ..Limits::default()
+        };
+        let mut com = limited(COUNT, limits);
+        let event = com.run(&mut VecDeque::new(), &mut vec![]).unwrap();
+        assert_eq!(event, StopEvent::LimitExceeded(Limit::Steps(10)));
+        assert_eq!(com.peek(7), 5);
+        assert_eq!(
+            com.step(None),
+            Err(ExecutionError::LimitExceeded(Limit::Steps(10)))
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let limits = Limits {
+            timeout: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        };
+        let mut com = limited(COUNT, limits);
+        let fresh = com.clone();
+        let (_, event) = com.accumulate_output_until_action().unwrap();
+        assert_eq!(
+            event,
+            StopEvent::LimitExceeded(Limit::Timeout(Duration::from_millis(20)))
+        );
+
+        // a clone made once the original ran out of time has time left
+        let mut clone = fresh.clone();
+        assert!(fresh.guard.as_ref().unwrap().check().is_err());
+        assert_eq!(clone.step(None).map(|(halted, _)| halted), Ok(false));
+    }
+
+    #[test]
+    fn test_detects_loops() {
+        // flips a flag back and forth, outputting it each time
+        let src = "
+            loop:   eq [f], #0, [f]
+                    out [f]
+                    jz #0, #loop
+            f:      data 0
+        ";
+        let limits = Limits {
+            detect_loops: true,
+            ..Limits::default()
+        };
+        let mut com = limited(src, limits.clone());
+        let mut out = vec![];
+        let event = com.run(&mut VecDeque::new(), &mut out).unwrap();
+        assert_eq!(
+            event,
+            StopEvent::LimitExceeded(Limit::Loop { pos: 4, period: 6 })
+        );
+        assert!(out.len() < 10);
+
+        // a loop that keeps reading input is not endless, it may get new values
+        let mut com = limited("loop: in [x]\njz #0, #loop\nx: data 0", limits);
+        let event = com.run(&mut VecDeque::from(vec![0; 100]), &mut vec![]);
+        assert_eq!(event, Ok(StopEvent::WaitingOnInput));
+    }
+}
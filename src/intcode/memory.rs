@@ -0,0 +1,297 @@
+use std::{collections::HashMap, sync::Arc};
+
+const PAGE_BITS: usize = 9;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+/// pages below this index live in a flat table, the rest in a map so a
+/// stray write to a huge address does not allocate a huge table
+const DENSE_PAGES: usize = 1 << 12;
+
+type Page<T> = Arc<Vec<T>>;
+
+/// What a machine keeps its cells in: `Memory`, or `HashMemory` to time it
+/// against. Unallocated cells read as 0.
+pub trait Store<T>: Clone + Send + Sync {
+    fn get(&self, addr: usize) -> T;
+
+    fn set(&mut self, addr: usize, val: T);
+
+    /// one past the highest address loaded or written
+    fn extent(&self) -> usize;
+
+    /// Allocated memory as `(start address, cells)` runs in address order.
+    /// Cells outside of these runs are 0.
+    fn segments(&self) -> Vec<(usize, Vec<T>)>;
+
+    /// rebuilds memory from `segments`, with `extent` as returned by `extent`
+    fn from_segments(segments: &[(usize, Vec<T>)], extent: usize) -> Self;
+
+    /// whether every cell holds the same value in both
+    fn same_cells(&self, other: &Self) -> bool;
+
+    /// Copy of every cell up to `extent`. Memory may reach far out, so
+    /// only for images known to be small, see `segments` otherwise.
+    fn to_vec(&self) -> Vec<T> {
+        (0..self.extent()).map(|a| self.get(a)).collect()
+    }
+}
+
+/// Intcode memory: fixed size pages allocated on first write and shared
+/// between clones until one of them writes to it. Unallocated cells read as 0.
+#[derive(Clone)]
+pub struct Memory<T = isize> {
+    dense: Vec<Option<Page<T>>>,
+    sparse: HashMap<usize, Page<T>>,
+    /// one past the highest address loaded or written
+    len: usize,
+}
+
+impl<T: Clone + Default> Default for Memory<T> {
+    fn default() -> Memory<T> {
+        Memory {
+            dense: vec![],
+            sparse: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T: Clone + Default> From<Vec<T>> for Memory<T> {
+    fn from(d: Vec<T>) -> Memory<T> {
+        let len = d.len();
+        let dense = d
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = chunk.to_vec();
+                page.resize(PAGE_SIZE, T::default());
+                Some(Arc::new(page))
+            })
+            .collect();
+
+        Memory {
+            dense,
+            sparse: HashMap::new(),
+            len,
+        }
+    }
+}
+
+impl<T: Clone + Default + PartialEq + Send + Sync> Store<T> for Memory<T> {
+    fn get(&self, addr: usize) -> T {
+        let (page, offset) = (addr >> PAGE_BITS, addr & (PAGE_SIZE - 1));
+        let page = if page < DENSE_PAGES {
+            self.dense.get(page).and_then(|p| p.as_ref())
+        } else {
+            self.sparse.get(&page)
+        };
+        page.map_or_else(T::default, |p| p[offset].clone())
+    }
+
+    fn set(&mut self, addr: usize, val: T) {
+        self.len = self.len.max(addr + 1);
+        let (page, offset) = (addr >> PAGE_BITS, addr & (PAGE_SIZE - 1));
+        let slot = if page < DENSE_PAGES {
+            if page >= self.dense.len() {
+                self.dense.resize(page + 1, None);
+            }
+            self.dense[page].get_or_insert_with(|| Arc::new(vec![T::default(); PAGE_SIZE]))
+        } else {
+            self.sparse
+                .entry(page)
+                .or_insert_with(|| Arc::new(vec![T::default(); PAGE_SIZE]))
+        };
+        Arc::make_mut(slot)[offset] = val;
+    }
+
+    fn extent(&self) -> usize {
+        self.len
+    }
+
+    fn segments(&self) -> Vec<(usize, Vec<T>)> {
+        let mut pages = self
+            .dense
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.as_ref().map(|p| (i, p)))
+            .chain(self.sparse.iter().map(|(i, p)| (*i, p)))
+            .collect::<Vec<_>>();
+        pages.sort_by_key(|(i, _)| *i);
+
+        let mut segments: Vec<(usize, Vec<T>)> = vec![];
+        for (i, page) in pages {
+            let start = i << PAGE_BITS;
+            let cells = &page[..PAGE_SIZE.min(self.len.saturating_sub(start))];
+            match segments.last_mut() {
+                Some((s, run)) if *s + run.len() == start => run.extend_from_slice(cells),
+                _ => segments.push((start, cells.to_vec())),
+            }
+        }
+        segments
+    }
+
+    fn from_segments(segments: &[(usize, Vec<T>)], extent: usize) -> Memory<T> {
+        let mut mem = Memory::default();
+        for (start, cells) in segments.iter() {
+            cells
+                .iter()
+                .enumerate()
+                .for_each(|(i, v)| mem.set(start + i, v.clone()));
+        }
+        mem.len = mem.len.max(extent);
+        mem
+    }
+
+    /// Pages still shared between clones are not compared cell by cell.
+    fn same_cells(&self, other: &Memory<T>) -> bool {
+        let same = |a: Option<&Page<T>>, b: Option<&Page<T>>| match (a, b) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b) || a == b,
+            (Some(p), None) | (None, Some(p)) => p.iter().all(|v| *v == T::default()),
+            (None, None) => true,
+        };
+        (0..self.dense.len().max(other.dense.len()))
+            .all(|i| same(self.dense_page(i), other.dense_page(i)))
+            && self
+                .sparse
+                .keys()
+                .chain(other.sparse.keys())
+                .all(|i| same(self.sparse.get(i), other.sparse.get(i)))
+    }
+}
+
+impl<T> Memory<T> {
+    fn dense_page(&self, i: usize) -> Option<&Page<T>> {
+        self.dense.get(i).and_then(|p| p.as_ref())
+    }
+}
+
+/// Every cell in a `HashMap`, as memory used to be kept. Slower than
+/// `Memory`, only there for the bench command to time the pages against.
+#[derive(Clone, Default)]
+pub struct HashMemory<T = isize> {
+    cells: HashMap<usize, T>,
+    len: usize,
+}
+
+impl<T: Clone + Default + PartialEq + Send + Sync> Store<T> for HashMemory<T> {
+    fn get(&self, addr: usize) -> T {
+        self.cells.get(&addr).cloned().unwrap_or_default()
+    }
+
+    fn set(&mut self, addr: usize, val: T) {
+        self.len = self.len.max(addr + 1);
+        self.cells.insert(addr, val);
+    }
+
+    fn extent(&self) -> usize {
+        self.len
+    }
+
+    fn segments(&self) -> Vec<(usize, Vec<T>)> {
+        let mut addrs = self.cells.keys().copied().collect::<Vec<_>>();
+        addrs.sort_unstable();
+        let mut segments: Vec<(usize, Vec<T>)> = vec![];
+        for addr in addrs {
+            let v = self.cells[&addr].clone();
+            match segments.last_mut() {
+                Some((s, run)) if *s + run.len() == addr => run.push(v),
+                _ => segments.push((addr, vec![v])),
+            }
+        }
+        segments
+    }
+
+    fn from_segments(segments: &[(usize, Vec<T>)], extent: usize) -> HashMemory<T> {
+        let cells = segments
+            .iter()
+            .flat_map(|(start, run)| run.iter().enumerate().map(move |(i, v)| (start + i, v)))
+            .map(|(a, v)| (a, v.clone()))
+            .collect::<HashMap<_, _>>();
+        let len = cells.keys().max().map_or(0, |a| a + 1).max(extent);
+        HashMemory { cells, len }
+    }
+
+    fn same_cells(&self, other: &HashMemory<T>) -> bool {
+        let zero = T::default();
+        let same = |a: &HashMemory<T>, b: &HashMemory<T>| {
+            a.cells
+                .iter()
+                .all(|(addr, v)| *v == *b.cells.get(addr).unwrap_or(&zero))
+        };
+        same(self, other) && same(other, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_set() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+        assert_eq!(mem.get(2), 3);
+        assert_eq!(mem.get(3), 0);
+        assert_eq!(mem.get(1 << 40), 0);
+        assert_eq!(mem.len, 3);
+
+        mem.set(PAGE_SIZE * 3 + 1, 7);
+        mem.set(1 << 40, 8);
+        assert_eq!(mem.get(PAGE_SIZE * 3 + 1), 7);
+        assert_eq!(mem.get(PAGE_SIZE * 3), 0);
+        assert_eq!(mem.get(1 << 40), 8);
+        assert_eq!(mem.len, (1 << 40) + 1);
+        assert_eq!(mem.dense.len(), 4);
+
+        let segments = mem.segments();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[1].0, PAGE_SIZE * 3);
+        assert_eq!(segments[2], ((1 << 40) & !(PAGE_SIZE - 1), vec![8]));
+
+        let restored = Memory::from_segments(&segments, mem.extent());
+        assert_eq!(restored.segments(), segments);
+        assert_eq!(restored.extent(), mem.extent());
+        assert!(restored.same_cells(&mem));
+
+        // an allocated page of zeros is the same as no page at all
+        let mut other = mem.clone();
+        other.set(PAGE_SIZE * 9, 5);
+        assert!(!other.same_cells(&mem));
+        other.set(PAGE_SIZE * 9, 0);
+        assert!(other.same_cells(&mem) && mem.same_cells(&other));
+    }
+
+    #[test]
+    fn test_hashed() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+        mem.set(1 << 40, 8);
+        let mut hashed = HashMemory::from_segments(&mem.segments(), mem.extent());
+        assert_eq!((hashed.get(2), hashed.get(1 << 40)), (3, 8));
+        assert_eq!(hashed.extent(), mem.extent());
+        // the pages hold zeros up to the end of theirs
+        let cells = |m: Vec<(usize, Vec<isize>)>| {
+            m.into_iter()
+                .flat_map(|(s, run)| run.into_iter().enumerate().map(move |(i, v)| (s + i, v)))
+                .filter(|(_, v)| *v != 0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(cells(hashed.segments()), cells(mem.segments()));
+
+        let copy = hashed.clone();
+        hashed.set(PAGE_SIZE * 2, 5);
+        assert_eq!(hashed.get(PAGE_SIZE * 2), 5);
+        assert!(!copy.same_cells(&hashed));
+        hashed.set(PAGE_SIZE * 2, 0);
+        assert!(copy.same_cells(&hashed));
+    }
+
+    #[test]
+    fn test_clones_copy_on_write() {
+        let mut a = Memory::from(vec![0; PAGE_SIZE * 2]);
+        let b = a.clone();
+        a.set(PAGE_SIZE + 5, 1);
+
+        assert_eq!(b.get(PAGE_SIZE + 5), 0);
+        let shared =
+            |i: usize| Arc::ptr_eq(a.dense[i].as_ref().unwrap(), b.dense[i].as_ref().unwrap());
+        assert!(shared(0));
+        assert!(!shared(1));
+    }
+}
@@ -1,27 +1,159 @@
-use std::error::Error;
+use std::{error::Error, fmt};
 
-#[derive(Clone, Copy, PartialEq)]
+use super::opcode::Opcode;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum OpMode {
     Position,
     Immediate,
     Relative,
 }
 
-pub fn parse_op_mode(code: isize, count: isize) -> Result<Vec<OpMode>, Box<dyn Error>> {
-    (0..count).into_iter().try_fold(vec![], |mut acc, curr| {
-        let pos = code % 10isize.pow(curr as u32 + 1) / 10isize.pow(curr as u32);
-        let m = match pos {
-            0 => OpMode::Position,
-            1 => OpMode::Immediate,
-            2 => OpMode::Relative,
-            _ => {
-                return Err(format!("operation mode {} is not supported", pos)
-                    .to_string()
-                    .into());
+impl OpMode {
+    pub fn from_digit(d: isize) -> Option<OpMode> {
+        match d {
+            0 => Some(OpMode::Position),
+            1 => Some(OpMode::Immediate),
+            2 => Some(OpMode::Relative),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DecodeError {
+    UnknownOpcode,
+    /// the mode digit that is not 0, 1 or 2
+    InvalidMode(isize),
+    WriteInImmediateMode,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode => write!(f, "unknown opcode"),
+            DecodeError::InvalidMode(d) => write!(f, "operation mode {} is not supported", d),
+            DecodeError::WriteInImmediateMode => write!(f, "write parameter in immediate mode"),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// An opcode cell split into its instruction and parameter modes. Modes past
+/// the instruction's arity read as `Position`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Decoded {
+    code: u8,
+    arity: u8,
+    modes: [OpMode; 3],
+}
+
+impl Decoded {
+    /// Decodes an opcode cell of the standard instruction set. Mode digits
+    /// past the instruction's parameters are ignored, like the leading zeros
+    /// they normally are.
+    pub fn new(cell: isize) -> Result<Decoded, DecodeError> {
+        let op = Opcode::from_code(cell % 100).ok_or(DecodeError::UnknownOpcode)?;
+        Decoded::split(cell, op.arity(), op.writes())
+    }
+
+    /// Decodes a cell whose instruction takes `arity` parameters, at most 3,
+    /// the last of them a write target if `writes`.
+    pub fn split(cell: isize, arity: usize, writes: bool) -> Result<Decoded, DecodeError> {
+        let mut modes = [OpMode::Position; 3];
+        let mut digits = cell / 100;
+        for m in modes.iter_mut().take(arity) {
+            *m = OpMode::from_digit(digits % 10).ok_or(DecodeError::InvalidMode(digits % 10))?;
+            digits /= 10;
+        }
+        if writes && modes[arity - 1] == OpMode::Immediate {
+            return Err(DecodeError::WriteInImmediateMode);
+        }
+        Ok(Decoded {
+            code: (cell % 100) as u8,
+            arity: arity as u8,
+            modes,
+        })
+    }
+
+    pub fn code(&self) -> isize {
+        self.code as isize
+    }
+
+    /// the standard instruction with this opcode
+    pub fn op(&self) -> Option<Opcode> {
+        Opcode::from_code(self.code())
+    }
+
+    /// instruction length in cells, opcode included
+    pub fn size(&self) -> usize {
+        self.arity as usize + 1
+    }
+
+    /// the mode of every parameter
+    pub fn modes(&self) -> &[OpMode] {
+        &self.modes[..self.arity as usize]
+    }
+
+    /// the mode of parameter `i`, counting from 0
+    pub fn mode(&self, i: usize) -> OpMode {
+        self.modes[i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::opcode::ALL;
+
+    const MODES: [OpMode; 3] = [OpMode::Position, OpMode::Immediate, OpMode::Relative];
+
+    /// every way to pick a mode for each of `arity` parameters
+    fn combinations(arity: usize) -> Vec<Vec<usize>> {
+        (0..3usize.pow(arity as u32))
+            .map(|n| (0..arity).map(|i| n / 3usize.pow(i as u32) % 3).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_all_modes() {
+        for op in ALL.iter().copied() {
+            for digits in combinations(op.arity()) {
+                let cell =
+                    digits.iter().rev().fold(0, |acc, d| acc * 10 + *d as isize) * 100 + op.code();
+                let modes = digits.iter().map(|d| MODES[*d]).collect::<Vec<_>>();
+
+                let immediate_write = op.writes() && modes.last() == Some(&OpMode::Immediate);
+                match Decoded::new(cell) {
+                    Ok(d) => {
+                        assert!(!immediate_write, "{} decoded", cell);
+                        assert_eq!(d.op(), Some(op));
+                        assert_eq!(d.modes(), &modes[..]);
+                    }
+                    Err(e) => {
+                        assert!(immediate_write, "{} failed", cell);
+                        assert_eq!(e, DecodeError::WriteInImmediateMode);
+                    }
+                }
             }
-        };
+        }
+    }
 
-        acc.push(m);
-        Ok(acc)
-    })
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Decoded::new(301), Err(DecodeError::InvalidMode(3)));
+        assert_eq!(Decoded::new(21905), Err(DecodeError::InvalidMode(9)));
+        assert_eq!(Decoded::new(1104), Ok(Decoded::new(104).unwrap()));
+        assert_eq!(Decoded::new(2203).unwrap().mode(0), OpMode::Relative);
+        assert_eq!(Decoded::new(103), Err(DecodeError::WriteInImmediateMode));
+        for cell in [0, 10, 42, 98, 100, -1, -99, -101] {
+            assert_eq!(
+                Decoded::new(cell),
+                Err(DecodeError::UnknownOpcode),
+                "{}",
+                cell
+            );
+        }
+    }
 }
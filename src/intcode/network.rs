@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+
+use super::{trace::Record, ExecutionError, IntcodeComputer, StopEvent};
+
+#[derive(Debug, PartialEq)]
+pub enum NetworkStop {
+    /// every machine halted
+    AllHalted,
+    /// some machines are still running but all of them wait on empty queues
+    Deadlock,
+    /// in a router network, a full round passed without any packet being sent
+    Idle,
+    /// an access of machine `machine` touched one of its watchpoints, the
+    /// network carries on from there when run again
+    Watchpoint { machine: usize, hit: Record },
+}
+
+enum Routing {
+    /// outputs of machine i are copied to the queue of every listed machine
+    Edges(Vec<Vec<usize>>),
+    /// outputs are `(destination, x, y)` packets, machines read -1 when
+    /// their queue is empty and packets for `nat` are kept by the network
+    Router { nat: Option<isize> },
+}
+
+/// Runs interconnected machines round-robin, each reading from its own queue.
+pub struct Network {
+    machines: Vec<IntcodeComputer>,
+    queues: Vec<VecDeque<isize>>,
+    halted: Vec<bool>,
+    outputs: Vec<Vec<isize>>,
+    routing: Routing,
+    /// last packet sent to the NAT address, if any
+    nat_packet: Option<(isize, isize)>,
+}
+
+impl Network {
+    /// Builds a network from a directed graph, `edges[i]` listing where the
+    /// outputs of machine i go. Fails unless there is one list per machine
+    /// and every edge leads to one.
+    pub fn new(machines: Vec<IntcodeComputer>, edges: Vec<Vec<usize>>) -> Result<Network, String> {
+        let n = machines.len();
+        if edges.len() != n {
+            return Err(format!("{} edge lists for {} machines", edges.len(), n));
+        }
+        if let Some((from, to)) = edges
+            .iter()
+            .enumerate()
+            .flat_map(|(i, e)| e.iter().map(move |to| (i, *to)))
+            .find(|(_, to)| *to >= n)
+        {
+            return Err(format!("edge from {} to unknown machine {}", from, to));
+        }
+        Ok(Network::with_routing(machines, Routing::Edges(edges)))
+    }
+
+    /// each machine feeds the next one, the last one feeds nobody
+    pub fn pipeline(machines: Vec<IntcodeComputer>) -> Network {
+        let n = machines.len();
+        let edges = (0..n)
+            .map(|i| if i + 1 < n { vec![i + 1] } else { vec![] })
+            .collect();
+        Network::new(machines, edges).expect("every edge leads to a machine")
+    }
+
+    /// each machine feeds the next one, the last one feeds the first
+    pub fn ring(machines: Vec<IntcodeComputer>) -> Network {
+        let n = machines.len();
+        let edges = (0..n).map(|i| vec![(i + 1) % n]).collect();
+        Network::new(machines, edges).expect("every edge leads to a machine")
+    }
+
+    /// `source` feeds every other machine
+    pub fn broadcast(machines: Vec<IntcodeComputer>, source: usize) -> Network {
+        let n = machines.len();
+        let edges = (0..n)
+            .map(|i| {
+                if i == source {
+                    (0..n).filter(|j| *j != source).collect()
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        Network::new(machines, edges).expect("every edge leads to a machine")
+    }
+
+    /// Packet switched network: machine i first reads its address i, then
+    /// sends `(destination, x, y)` triples. Packets for `nat` are held back
+    /// until the network idles, see `wake_from_nat`, packets for unknown
+    /// addresses are dropped.
+    pub fn router(machines: Vec<IntcodeComputer>, nat: Option<isize>) -> Network {
+        let mut net = Network::with_routing(machines, Routing::Router { nat });
+        for (i, q) in net.queues.iter_mut().enumerate() {
+            q.push_back(i as isize);
+        }
+        net
+    }
+
+    fn with_routing(machines: Vec<IntcodeComputer>, routing: Routing) -> Network {
+        let n = machines.len();
+        Network {
+            machines,
+            queues: vec![VecDeque::new(); n],
+            halted: vec![false; n],
+            outputs: vec![vec![]; n],
+            routing,
+            nat_packet: None,
+        }
+    }
+
+    /// queues a value for machine `to`
+    pub fn send(&mut self, to: usize, v: isize) {
+        self.queues[to].push_back(v);
+    }
+
+    /// everything machine `i` has output so far
+    pub fn outputs(&self, i: usize) -> &[isize] {
+        &self.outputs[i]
+    }
+
+    pub fn nat_packet(&self) -> Option<(isize, isize)> {
+        self.nat_packet
+    }
+
+    /// Hands the last packet the NAT received to machine 0, as after the
+    /// network went idle. Returns the packet, if there was one.
+    pub fn wake_from_nat(&mut self) -> Option<(isize, isize)> {
+        let (x, y) = self.nat_packet?;
+        self.queues[0].extend(vec![x, y]);
+        Some((x, y))
+    }
+
+    /// Runs every machine in turn until all halted, no machine can make
+    /// progress any more or one hits a watchpoint.
+    pub fn run(&mut self) -> Result<NetworkStop, ExecutionError> {
+        loop {
+            let mut progressed = false;
+            for i in 0..self.machines.len() {
+                if self.halted[i] {
+                    continue;
+                }
+                let (p, hit) = self.run_machine(i)?;
+                if let Some(hit) = hit {
+                    return Ok(NetworkStop::Watchpoint { machine: i, hit });
+                }
+                progressed |= p;
+            }
+
+            if self.halted.iter().all(|h| *h) {
+                return Ok(NetworkStop::AllHalted);
+            }
+            if !progressed {
+                return Ok(match self.routing {
+                    Routing::Edges(_) => NetworkStop::Deadlock,
+                    Routing::Router { .. } => NetworkStop::Idle,
+                });
+            }
+        }
+    }
+
+    /// runs machine i until it halts, starves or hits a watchpoint, returning
+    /// whether it consumed real input or produced output, and the hit
+    fn run_machine(&mut self, i: usize) -> Result<(bool, Option<Record>), ExecutionError> {
+        let polls = matches!(self.routing, Routing::Router { .. });
+        let queue = &mut self.queues[i];
+        let mut consumed = false;
+        let mut polled = false;
+        let mut input = || match queue.pop_front() {
+            Some(v) => {
+                consumed = true;
+                Some(v)
+            }
+            None if polls && !polled => {
+                polled = true;
+                Some(-1)
+            }
+            None => None,
+        };
+
+        let mut outputs = vec![];
+        let mut hit = None;
+        match self.machines[i].run(&mut input, &mut outputs)? {
+            StopEvent::Finished => self.halted[i] = true,
+            StopEvent::LimitExceeded(l) => return Err(ExecutionError::LimitExceeded(l)),
+            StopEvent::Watchpoint(h) => hit = Some(h),
+            StopEvent::WaitingOnInput => {}
+        }
+
+        let produced = !outputs.is_empty();
+        let start = self.outputs[i].len();
+        self.outputs[i].extend(outputs);
+        match &self.routing {
+            Routing::Edges(edges) => {
+                for to in edges[i].iter() {
+                    self.queues[*to].extend(self.outputs[i][start..].iter());
+                }
+            }
+            Routing::Router { nat } => {
+                // a machine may pause mid packet, deliver the ones completed by this run
+                let sent = &self.outputs[i];
+                for k in start / 3..sent.len() / 3 {
+                    let (to, x, y) = (sent[3 * k], sent[3 * k + 1], sent[3 * k + 2]);
+                    if Some(to) == *nat {
+                        self.nat_packet = Some((x, y));
+                    } else if to >= 0 && (to as usize) < self.queues.len() {
+                        self.queues[to as usize].extend(vec![x, y]);
+                    }
+                }
+            }
+        }
+        Ok((consumed || produced, hit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm, trace::Access};
+
+    fn machines(src: &str, n: usize) -> Vec<IntcodeComputer> {
+        vec![intcode::new(asm::assemble(src).unwrap()); n]
+    }
+
+    // waits for one packet and forwards it to the NAT at 255, forever
+    const FORWARD: &str = "
+                in [addr]
+        poll:   in [x]
+                eq [x], #-1, [t]
+                jnz [t], #poll
+                in [y]
+                out #255
+                out [x]
+                out [y]
+                jz #0, #poll
+        addr:   data 0
+        x:      data 0
+        y:      data 0
+        t:      data 0
+    ";
+
+    #[test]
+    fn test_broadcast() {
+        let mut ms = machines("in [0]\nin [1]\nadd [0], [1], [0]\nout [0]\nhlt", 3);
+        ms[0] = intcode::new(vec![104, 1, 104, 2, 99]);
+        let mut net = Network::broadcast(ms, 0);
+        assert_eq!(net.run().unwrap(), NetworkStop::AllHalted);
+        assert_eq!(net.outputs(1), &[3]);
+        assert_eq!(net.outputs(2), &[3]);
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let mut net = Network::ring(machines("in [0]\nout [0]\nhlt", 2));
+        assert_eq!(net.run().unwrap(), NetworkStop::Deadlock);
+
+        net.send(1, 7);
+        assert_eq!(net.run().unwrap(), NetworkStop::AllHalted);
+        assert_eq!(net.outputs(0), &[7]);
+    }
+
+    #[test]
+    fn test_checks_edges() {
+        let ms = || machines("hlt", 2);
+        assert!(Network::new(ms(), vec![vec![1], vec![]]).is_ok());
+        assert_eq!(
+            Network::new(ms(), vec![vec![1]]).err(),
+            Some("1 edge lists for 2 machines".to_string())
+        );
+        assert_eq!(
+            Network::new(ms(), vec![vec![1], vec![0, 2]]).err(),
+            Some("edge from 1 to unknown machine 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut ms = machines("in [0]\nout [0]\nhlt", 2);
+        ms[1].enable_tracing().watch(0..=0, Some(Access::Write));
+        let mut net = Network::pipeline(ms);
+        net.send(0, 7);
+        match net.run().unwrap() {
+            NetworkStop::Watchpoint { machine: 1, hit } => assert_eq!(hit.value, 7),
+            stop => panic!("unexpected stop {:?}", stop),
+        }
+        assert_eq!(net.run().unwrap(), NetworkStop::AllHalted);
+        assert_eq!(net.outputs(1), &[7]);
+    }
+
+    #[test]
+    fn test_router_and_nat() {
+        let mut net = Network::router(machines(FORWARD, 3), Some(255));
+        assert_eq!(net.run().unwrap(), NetworkStop::Idle);
+        assert_eq!(net.nat_packet(), None);
+
+        net.send(2, 5);
+        net.send(2, 6);
+        assert_eq!(net.run().unwrap(), NetworkStop::Idle);
+        assert_eq!(net.outputs(2), &[255, 5, 6]);
+        assert_eq!(net.nat_packet(), Some((5, 6)));
+
+        assert_eq!(net.wake_from_nat(), Some((5, 6)));
+        assert_eq!(net.run().unwrap(), NetworkStop::Idle);
+        assert_eq!(net.outputs(0), &[255, 5, 6]);
+    }
+
+    #[test]
+    fn test_router_delivers_packets() {
+        // machine i sends (i, 10 * i) to machine i + 1, which forwards it to the NAT
+        let src = format!(
+            "
+                in [addr]
+                add [addr], #1, [dest]
+                out [dest]
+                out [addr]
+                mul [addr], #10, [y]
+                out [y]
+            {}
+        dest:   data 0",
+            FORWARD.replacen("in [addr]", "", 1)
+        );
+        let mut net = Network::router(machines(&src, 3), Some(255));
+        assert_eq!(net.run().unwrap(), NetworkStop::Idle);
+        assert_eq!(net.outputs(1), &[2, 1, 10, 255, 0, 0]);
+        assert_eq!(net.outputs(2), &[3, 2, 20, 255, 1, 10]);
+        assert_eq!(net.nat_packet(), Some((1, 10)));
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+};
+
+use super::{
+    cell::Cell,
+    disasm::{self, Line},
+    IntcodeComputer,
+};
+
+/// Cells to overwrite starting at `addr`, optionally only if they still
+/// hold `expected`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub addr: usize,
+    pub values: Vec<isize>,
+    pub expected: Option<Vec<isize>>,
+}
+
+impl Change {
+    /// address right after the last cell changed
+    pub fn end(&self) -> usize {
+        self.addr + self.values.len()
+    }
+}
+
+/// Changes to make to a program before running it, one per line:
+///
+/// ```text
+/// # free play
+/// 0=2
+/// # two cells, and what they held before
+/// 12=1105,1 (was 1106,0)
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Patch {
+    pub changes: Vec<Change>,
+}
+
+/// a cell that does not hold the value a patch expects
+#[derive(Debug, PartialEq)]
+pub struct Mismatch<T> {
+    pub addr: usize,
+    pub expected: T,
+    pub found: T,
+}
+
+impl<T: fmt::Display> fmt::Display for Mismatch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "patch expects {} at address {}, found {}",
+            self.expected, self.addr, self.found
+        )
+    }
+}
+
+fn parse_list(s: &str) -> Option<Vec<isize>> {
+    s.split(',').map(|v| v.trim().parse().ok()).collect()
+}
+
+/// values as a patch writes them, separated by commas
+pub fn join(values: &[isize]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Patch {
+    pub fn parse(src: &str) -> Result<Patch, String> {
+        let mut changes = vec![];
+        for (n, line) in src.lines().enumerate() {
+            let err = |msg: &str| format!("line {}: {}", n + 1, msg);
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (addr, rest) = line
+                .split_once('=')
+                .ok_or_else(|| err("expecting address=value"))?;
+            let addr = addr
+                .trim()
+                .parse()
+                .map_err(|_| err("cannot parse address"))?;
+            let (new, old) = match rest.split_once('(') {
+                Some((new, old)) => {
+                    let old = old
+                        .trim()
+                        .strip_prefix("was")
+                        .and_then(|o| o.strip_suffix(')'))
+                        .ok_or_else(|| err("expecting (was old values)"))?;
+                    (new, Some(old))
+                }
+                None => (rest, None),
+            };
+            let values = parse_list(new).ok_or_else(|| err("cannot parse value"))?;
+            let expected = match old {
+                Some(old) => Some(parse_list(old).ok_or_else(|| err("cannot parse old value"))?),
+                None => None,
+            };
+            if expected.as_ref().is_some_and(|e| e.len() != values.len()) {
+                return Err(err("as many old values as new ones needed"));
+            }
+            changes.push(Change {
+                addr,
+                values,
+                expected,
+            });
+        }
+        Ok(Patch { changes })
+    }
+
+    /// Checks every expected value before changing anything, so a patch
+    /// that does not fit leaves the machine as it was.
+    pub fn apply<T: Cell>(&self, com: &mut IntcodeComputer<T>) -> Result<(), Mismatch<T>> {
+        for c in self.changes.iter() {
+            for (i, want) in c.expected.iter().flatten().enumerate() {
+                let (expected, found) = (T::from_isize(*want), com.peek(c.addr + i));
+                if expected != found {
+                    return Err(Mismatch {
+                        addr: c.addr + i,
+                        expected,
+                        found,
+                    });
+                }
+            }
+        }
+        for c in self.changes.iter() {
+            for (i, v) in c.values.iter().enumerate() {
+                com.set(c.addr + i, T::from_isize(*v));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.changes.iter() {
+            write!(f, "{}={}", c.addr, join(&c.values))?;
+            if let Some(old) = &c.expected {
+                write!(f, " (was {})", join(old))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The patch turning memory `a` into `b`, both in segments as
+/// `Store::segments` returns them, one change per run of differing cells.
+/// Cells outside of the segments count as 0.
+pub fn diff(a: &[(usize, Vec<isize>)], b: &[(usize, Vec<isize>)]) -> Patch {
+    // old and new value of every cell either side allocated
+    let mut cells: BTreeMap<usize, (isize, isize)> = BTreeMap::new();
+    for (start, values) in a.iter() {
+        for (i, v) in values.iter().enumerate() {
+            cells.entry(start + i).or_default().0 = *v;
+        }
+    }
+    for (start, values) in b.iter() {
+        for (i, v) in values.iter().enumerate() {
+            cells.entry(start + i).or_default().1 = *v;
+        }
+    }
+
+    let mut changes: Vec<Change> = vec![];
+    for (addr, (old, new)) in cells {
+        if old == new {
+            continue;
+        }
+        match changes.last_mut() {
+            Some(c) if c.end() == addr => {
+                c.values.push(new);
+                c.expected.get_or_insert_with(Vec::new).push(old);
+            }
+            _ => changes.push(Change {
+                addr,
+                values: vec![new],
+                expected: Some(vec![old]),
+            }),
+        }
+    }
+    Patch { changes }
+}
+
+/// Writes every change from `a` to `b` with the disassembly around it:
+/// lines of `a` touching the change prefixed with `-`, those of `b` with
+/// `+`, and up to `context` unchanged lines of `b` on either side.
+pub fn write_diff<W: Write>(
+    a: &[(usize, Vec<isize>)],
+    b: &[(usize, Vec<isize>)],
+    context: usize,
+    mut w: W,
+) -> io::Result<()> {
+    let (old, new) = (
+        disasm::disassemble_segments(a),
+        disasm::disassemble_segments(b),
+    );
+    let overlaps = |l: &Line, c: &Change| l.addr < c.end() && l.addr + l.size() > c.addr;
+
+    for c in diff(a, b).changes {
+        writeln!(w, "@@ {}..{} @@", c.addr, c.end())?;
+        let first = new.iter().position(|l| overlaps(l, &c));
+        let before = new
+            .iter()
+            .take(first.unwrap_or_else(|| new.iter().filter(|l| l.addr < c.addr).count()))
+            .collect::<Vec<_>>();
+        for l in before.iter().skip(before.len().saturating_sub(context)) {
+            writeln!(w, " {}", l)?;
+        }
+        for l in old.iter().filter(|l| overlaps(l, &c)) {
+            writeln!(w, "-{}", l)?;
+        }
+        for l in new.iter().filter(|l| overlaps(l, &c)) {
+            writeln!(w, "+{}", l)?;
+        }
+        for l in new.iter().filter(|l| l.addr >= c.end()).take(context) {
+            writeln!(w, " {}", l)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_parse() {
+        let patch = Patch::parse("# free play\n0=2\n\n12 = 1105, 1 (was 1106,0) # flip\n").unwrap();
+        assert_eq!(
+            patch.changes,
+            vec![
+                Change {
+                    addr: 0,
+                    values: vec![2],
+                    expected: None
+                },
+                Change {
+                    addr: 12,
+                    values: vec![1105, 1],
+                    expected: Some(vec![1106, 0])
+                },
+            ]
+        );
+        assert_eq!(Patch::parse(&patch.to_string()), Ok(patch));
+
+        assert_eq!(
+            Patch::parse("0=2\n1=x").err(),
+            Some("line 2: cannot parse value".to_string())
+        );
+        assert!(Patch::parse("1=2,3 (was 4)").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut com = intcode::new(vec![1, 0, 0, 0, 99]);
+        let patch = Patch::parse("0=2\n3=7 (was 1)").unwrap();
+        assert_eq!(
+            patch.apply(&mut com),
+            Err(Mismatch {
+                addr: 3,
+                expected: 1,
+                found: 0
+            })
+        );
+        // nothing changed
+        assert_eq!(com.memory(), vec![1, 0, 0, 0, 99]);
+
+        let patch = Patch::parse("0=2\n3=7 (was 0)\n6=1").unwrap();
+        assert_eq!(patch.apply(&mut com), Ok(()));
+        assert_eq!(com.memory(), vec![2, 0, 0, 7, 99, 0, 1]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = vec![(0, vec![1, 0, 0, 0, 99, 5, 6])];
+        let b = vec![(0, vec![2, 0, 0, 0, 99, 8, 9, 1])];
+        let patch = diff(&a, &b);
+        assert_eq!(patch.to_string(), "0=2 (was 1)\n5=8,9,1 (was 5,6,0)\n");
+
+        let mut com = intcode::new(a[0].1.clone());
+        patch.apply(&mut com).unwrap();
+        assert_eq!(com.memory(), b[0].1);
+
+        let mut out = vec![];
+        write_diff(&a, &b, 1, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "@@ 0..1 @@");
+        assert!(lines[1].starts_with("-     0: add"));
+        assert!(lines[2].starts_with("+     0: mul"));
+        assert!(lines[3].starts_with("      4: hlt"));
+        assert_eq!(lines[4], "@@ 5..8 @@");
+    }
+
+    #[test]
+    fn test_diff_far_out() {
+        // a write far out does not make either side copy memory up to it
+        let a = intcode::new(vec![1101, 2, 3, 1 << 40, 99]);
+        let mut b = a.clone();
+        b.run(&mut VecDeque::new(), &mut vec![]).unwrap();
+        let (a, b) = (a.segments(), b.segments());
+        let patch = diff(&a, &b);
+        assert_eq!(patch.to_string(), format!("{}=5 (was 0)\n", 1u64 << 40));
+
+        let mut out = vec![];
+        write_diff(&a, &b, 1, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            format!("@@ {}..{} @@", 1u64 << 40, (1u64 << 40) + 1)
+        );
+        // the last line of the program before it, as context
+        assert!(lines[1].starts_with("    509: data 0, 0, 0"));
+        assert!(lines[2].starts_with("+1099511627776: data 5 "));
+        assert_eq!(lines.len(), 3);
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use super::{disasm, opcode::Opcode};
+
+/// how many addresses the hot spot ranking lists
+const HOT_SPOTS: usize = 20;
+
+/// Execution counts gathered while profiling.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    /// executions per instruction address
+    pub by_addr: HashMap<usize, u64>,
+    pub by_opcode: HashMap<Opcode, u64>,
+    /// `(taken, not taken)` per jump instruction address
+    pub jumps: HashMap<usize, (u64, u64)>,
+}
+
+impl Profile {
+    pub fn total(&self) -> u64 {
+        self.by_addr.values().sum()
+    }
+
+    /// counts an instruction, by opcode too if it is a standard one
+    pub(super) fn count(&mut self, addr: usize, op: Option<Opcode>) {
+        *self.by_addr.entry(addr).or_default() += 1;
+        if let Some(op) = op {
+            *self.by_opcode.entry(op).or_default() += 1;
+        }
+    }
+
+    pub(super) fn jump(&mut self, addr: usize, taken: bool) {
+        let j = self.jumps.entry(addr).or_default();
+        if taken {
+            j.0 += 1;
+        } else {
+            j.1 += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Profile) {
+        for (a, n) in other.by_addr.iter() {
+            *self.by_addr.entry(*a).or_default() += n;
+        }
+        for (op, n) in other.by_opcode.iter() {
+            *self.by_opcode.entry(*op).or_default() += n;
+        }
+        for (a, (t, n)) in other.jumps.iter() {
+            let j = self.jumps.entry(*a).or_default();
+            j.0 += t;
+            j.1 += n;
+        }
+    }
+
+    /// Writes a ranking of the hottest addresses and opcodes, jump
+    /// statistics and a disassembly of `prog` annotated with the counts.
+    pub fn write_report<W: Write>(&self, prog: &[isize], mut w: W) -> io::Result<()> {
+        let total = self.total().max(1);
+        let pct = |n: u64| 100.0 * n as f64 / total as f64;
+        let text = |addr: usize| match disasm::decode(prog, addr) {
+            Some(ins) => ins.to_string(),
+            None => "?".to_string(),
+        };
+
+        writeln!(w, "{} instructions executed", self.total())?;
+        writeln!(w, "\nhot spots:")?;
+        let mut hot = self.by_addr.iter().collect::<Vec<_>>();
+        hot.sort_by_key(|(a, n)| (std::cmp::Reverse(**n), **a));
+        for (a, n) in hot.iter().take(HOT_SPOTS) {
+            writeln!(w, "{:>12} {:>6.2}% {:>6}: {}", n, pct(**n), a, text(**a))?;
+        }
+
+        writeln!(w, "\nopcodes:")?;
+        let mut ops = self.by_opcode.iter().collect::<Vec<_>>();
+        ops.sort_by_key(|(op, n)| (std::cmp::Reverse(**n), op.code()));
+        for (op, n) in ops {
+            writeln!(w, "{:>12} {:>6.2}% {}", n, pct(*n), op.mnemonic())?;
+        }
+
+        writeln!(w, "\njumps (taken / not taken):")?;
+        let mut jumps = self.jumps.iter().collect::<Vec<_>>();
+        jumps.sort_by_key(|(a, _)| **a);
+        for (a, (t, n)) in jumps {
+            writeln!(w, "{:>12} / {:<12} {:>6}: {}", t, n, a, text(*a))?;
+        }
+
+        // whatever ran, plus the code that could have run but did not
+        writeln!(w, "\nannotated disassembly:")?;
+        let addrs = disasm::trace_reachable(prog)
+            .keys()
+            .chain(self.by_addr.keys())
+            .copied()
+            .collect::<BTreeSet<_>>();
+        for a in addrs {
+            let n = self.by_addr.get(&a).copied().unwrap_or(0);
+            let line = format!("{:>12} {:>6}: {}", n, a, text(a));
+            match self.jumps.get(&a) {
+                Some((t, n)) => writeln!(w, "{:<60} ; taken {}, not taken {}", line, t, n)?,
+                None => writeln!(w, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Counts for one machine, added to a `Profile` shared with its clones when
+/// dropped so parallel runs do not contend for the lock on every step.
+pub(super) struct Profiler {
+    local: Profile,
+    shared: Arc<Mutex<Profile>>,
+}
+
+impl Profiler {
+    pub(super) fn new(shared: Arc<Mutex<Profile>>) -> Profiler {
+        Profiler {
+            local: Profile::default(),
+            shared,
+        }
+    }
+
+    pub(super) fn local(&mut self) -> &mut Profile {
+        &mut self.local
+    }
+
+    /// moves the counts so far to the shared profile
+    pub(super) fn flush(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.merge(&self.local);
+        }
+        self.local = Profile::default();
+    }
+}
+
+/// a clone starts counting from zero into the same shared profile
+impl Clone for Profiler {
+    fn clone(&self) -> Profiler {
+        Profiler::new(self.shared.clone())
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, asm};
+    use std::collections::VecDeque;
+
+    // counts down from its input, outputting every value
+    const COUNTDOWN: &str = "
+                in [n]
+        loop:   out [n]
+                add [n], #-1, [n]
+                jnz [n], #loop
+                hlt
+        n:      data 0
+    ";
+
+    #[test]
+    fn test_counts() {
+        let shared = Arc::new(Mutex::new(Profile::default()));
+        let prog = asm::assemble(COUNTDOWN).unwrap();
+        let mut com = intcode::new(prog.clone());
+        com.enable_profiling(shared.clone());
+
+        let mut clone = com.clone();
+        com.run(&mut VecDeque::from(vec![3]), &mut vec![]).unwrap();
+        clone
+            .run(&mut VecDeque::from(vec![2]), &mut vec![])
+            .unwrap();
+        assert_eq!(shared.lock().unwrap().total(), 0);
+        drop(com);
+        assert_eq!(shared.lock().unwrap().total(), 11);
+        drop(clone);
+
+        let p = shared.lock().unwrap();
+        assert_eq!(p.by_addr[&0], 2);
+        assert_eq!(p.by_addr[&2], 5);
+        assert_eq!(p.by_opcode[&Opcode::Add], 5);
+        assert_eq!(p.by_opcode[&Opcode::Hlt], 2);
+        assert_eq!(p.jumps[&8], (3, 2));
+        assert_eq!(p.total(), 2 + 3 * 5 + 2);
+
+        let mut report = vec![];
+        p.write_report(&prog, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("           5  26.32%      2: out [12]"));
+        assert!(report.contains("           3 / 2                 8: jnz [12], #2"));
+        assert!(report.contains("      8: jnz [12], #2"));
+        assert!(report.contains("#2                            ; taken 3, not taken 2\n"));
+        assert!(report.contains("     11: hlt\n"));
+    }
+}
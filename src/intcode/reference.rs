@@ -0,0 +1,177 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+};
+
+/// why a machine stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    Halted,
+    NeedsInput,
+    /// the instruction at `addr` could not run
+    Fault {
+        addr: usize,
+    },
+    StepLimit,
+}
+
+/// What a program did: its outputs, where it stopped, after how many
+/// instructions, and every cell it was loaded with or wrote.
+pub struct Outcome {
+    pub outputs: Vec<isize>,
+    pub stop: Stop,
+    pub steps: usize,
+    pub memory: HashMap<usize, isize>,
+}
+
+/// Runs `prog` for at most `max_steps` instructions, following the puzzle
+/// text and nothing else: no pre-decoding, no paging, no instrumentation.
+/// `IntcodeComputer` is checked against this, see `fuzz`.
+pub fn run(prog: &[isize], inputs: &[isize], max_steps: usize) -> Outcome {
+    let mut m = Machine {
+        mem: prog.iter().copied().enumerate().collect(),
+        pc: 0,
+        base: 0,
+    };
+    let mut inputs = inputs.iter().copied().collect::<VecDeque<_>>();
+    let mut outputs = vec![];
+    let mut steps = 0;
+    let stop = loop {
+        if steps == max_steps {
+            break Stop::StepLimit;
+        }
+        match m.step(&mut inputs, &mut outputs) {
+            Ok(()) => steps += 1,
+            Err(stop) => break stop,
+        }
+    };
+    Outcome {
+        outputs,
+        stop,
+        steps,
+        memory: m.mem,
+    }
+}
+
+struct Machine {
+    mem: HashMap<usize, isize>,
+    pc: usize,
+    base: isize,
+}
+
+impl Machine {
+    fn read(&self, addr: usize) -> isize {
+        self.mem.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// runs one instruction, or says why the machine stops instead
+    fn step(&mut self, inputs: &mut VecDeque<isize>, outputs: &mut Vec<isize>) -> Result<(), Stop> {
+        let fault = Stop::Fault { addr: self.pc };
+        let cell = self.read(self.pc);
+        let opcode = cell % 100;
+
+        // how many parameters, and whether the last one is written to
+        let (arity, writes) = match opcode {
+            1 | 2 | 7 | 8 => (3, true),
+            3 => (1, true),
+            4 | 9 => (1, false),
+            5 | 6 => (2, false),
+            99 => (0, false),
+            _ => return Err(fault),
+        };
+        let mut modes = vec![];
+        for i in 0..arity {
+            let mode = cell / 10isize.pow(i as u32 + 2) % 10;
+            if mode > 2 {
+                return Err(fault);
+            }
+            modes.push(mode);
+        }
+        if writes && modes[arity - 1] == 1 {
+            return Err(fault);
+        }
+
+        // where parameter `i` points, in position or relative mode
+        let address = |i: usize| -> Result<usize, Stop> {
+            let raw = self.read(self.pc + 1 + i);
+            let addr = match modes[i] {
+                2 => raw.checked_add(self.base).ok_or(fault)?,
+                _ => raw,
+            };
+            usize::try_from(addr).map_err(|_| fault)
+        };
+        let value = |i: usize| -> Result<isize, Stop> {
+            match modes[i] {
+                1 => Ok(self.read(self.pc + 1 + i)),
+                _ => Ok(self.read(address(i)?)),
+            }
+        };
+
+        match opcode {
+            1 => {
+                let sum = value(0)?.wrapping_add(value(1)?);
+                let to = address(2)?;
+                self.mem.insert(to, sum);
+            }
+            2 => {
+                let product = value(0)?.wrapping_mul(value(1)?);
+                let to = address(2)?;
+                self.mem.insert(to, product);
+            }
+            3 => {
+                let v = inputs.pop_front().ok_or(Stop::NeedsInput)?;
+                let to = address(0)?;
+                self.mem.insert(to, v);
+            }
+            4 => outputs.push(value(0)?),
+            5 | 6 => {
+                let (cond, to) = (value(0)?, value(1)?);
+                if (cond != 0) == (opcode == 5) {
+                    self.pc = usize::try_from(to).map_err(|_| fault)?;
+                    return Ok(());
+                }
+            }
+            7 => {
+                let less = value(0)? < value(1)?;
+                let to = address(2)?;
+                self.mem.insert(to, less as isize);
+            }
+            8 => {
+                let equal = value(0)? == value(1)?;
+                let to = address(2)?;
+                self.mem.insert(to, equal as isize);
+            }
+            9 => self.base = self.base.checked_add(value(0)?).ok_or(fault)?,
+            _ => return Err(Stop::Halted),
+        }
+        self.pc += 1 + arity;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_examples() {
+        // day 9 quine
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let out = run(&quine, &[], 1000);
+        assert_eq!(out.stop, Stop::Halted);
+        assert_eq!(out.outputs, quine);
+
+        // day 5: 1 if the input equals 8
+        let eq8 = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        assert_eq!(run(&eq8, &[8], 10).outputs, vec![1]);
+        let out = run(&eq8, &[], 10);
+        assert_eq!((out.stop, out.steps), (Stop::NeedsInput, 0));
+
+        let out = run(&[1, 0, 0, 0, 42], &[], 10);
+        assert_eq!(out.stop, Stop::Fault { addr: 4 });
+        assert_eq!(out.memory[&0], 2);
+        assert_eq!(run(&[1105, 1, 0], &[], 10).stop, Stop::StepLimit);
+    }
+}
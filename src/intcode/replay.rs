@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    io::{self, Write},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::{cell::Cell, ExecutionError, IntcodeComputer};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Io<T = isize> {
+    In(T),
+    Out(T),
+    /// a write made from outside the program, e.g. patching in a noun and verb
+    Set(usize, T),
+}
+
+/// something a machine did, after executing `step` instructions
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event<T = isize> {
+    pub step: u64,
+    pub io: Io<T>,
+}
+
+impl<T: fmt::Display> fmt::Display for Event<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.io {
+            Io::In(v) => write!(f, "{} in {}", self.step, v),
+            Io::Out(v) => write!(f, "{} out {}", self.step, v),
+            Io::Set(addr, v) => write!(f, "{} set {} {}", self.step, addr, v),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineLog<T = isize> {
+    /// the machine this one was cloned from and how many of its events came
+    /// before, `None` for a machine that started from the loaded program
+    pub parent: Option<(usize, usize)>,
+    /// steps made in all, counting those made before the clone
+    pub steps: u64,
+    pub events: Vec<Event<T>>,
+}
+
+/// The I/O of every machine that recorded into it, see
+/// `IntcodeComputer::enable_recording`. Machines are numbered in the order
+/// they started recording, which for clones made in parallel is not fixed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IoLog<T = isize> {
+    pub machines: BTreeMap<usize, MachineLog<T>>,
+}
+
+impl<T> Default for IoLog<T> {
+    fn default() -> IoLog<T> {
+        IoLog {
+            machines: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> IoLog<T> {
+    fn add(&mut self, parent: Option<(usize, usize)>, steps: u64) -> usize {
+        let id = self.machines.keys().next_back().map_or(0, |id| id + 1);
+        self.machines.insert(
+            id,
+            MachineLog {
+                parent,
+                steps,
+                events: vec![],
+            },
+        );
+        id
+    }
+}
+
+/// where a replayed machine first did something other than recorded
+#[derive(Debug, PartialEq)]
+pub struct Divergence<T = isize> {
+    pub machine: usize,
+    /// index into the machine's `history`
+    pub index: usize,
+    pub expected: Option<Event<T>>,
+    pub actual: Option<Event<T>>,
+}
+
+impl<T: fmt::Display> fmt::Display for Divergence<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |e: &Option<Event<T>>| match e {
+            Some(e) => e.to_string(),
+            None => "nothing".to_string(),
+        };
+        write!(
+            f,
+            "machine {} event {}: expected {}, got {}",
+            self.machine,
+            self.index,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+impl<T: Cell> IoLog<T> {
+    /// everything `machine` did since the program was loaded, including what
+    /// the machines it was cloned from did before
+    pub fn history(&self, machine: usize) -> Vec<Event<T>> {
+        // the machine and its ancestors, each with the events it inherited;
+        // a parent always started recording before its clones
+        let mut chain = vec![];
+        let mut next = Some((machine, None));
+        while let Some((id, inherited)) = next {
+            let log = match self.machines.get(&id) {
+                Some(log) => log,
+                None => break,
+            };
+            chain.push((log, inherited));
+            next = log
+                .parent
+                .filter(|(parent, _)| *parent < id)
+                .map(|(parent, n)| (parent, Some(n)));
+        }
+
+        let mut events = vec![];
+        for (log, inherited) in chain.into_iter().rev() {
+            events.extend(log.events.iter().cloned());
+            // what the clone inherited from this machine
+            if let Some(n) = inherited {
+                events.truncate(n);
+            }
+        }
+        events
+    }
+
+    /// Runs a copy of `prog` for every recorded machine, feeding it the
+    /// recorded inputs, and compares what it does to the recording.
+    pub fn replay(
+        &self,
+        prog: &IntcodeComputer<T>,
+    ) -> Result<Option<Divergence<T>>, ExecutionError> {
+        for id in self.machines.keys() {
+            if let Some(d) = self.replay_machine(*id, prog)? {
+                return Ok(Some(d));
+            }
+        }
+        Ok(None)
+    }
+
+    fn replay_machine(
+        &self,
+        id: usize,
+        prog: &IntcodeComputer<T>,
+    ) -> Result<Option<Divergence<T>>, ExecutionError> {
+        let expected = self.history(id);
+        let steps = self.machines[&id].steps;
+        let mut inputs = expected
+            .iter()
+            .filter_map(|e| match &e.io {
+                Io::In(v) => Some(v.clone()),
+                _ => None,
+            })
+            .collect::<VecDeque<_>>();
+        let mut sets = expected
+            .iter()
+            .filter_map(|e| match &e.io {
+                Io::Set(addr, v) => Some((e.step, *addr, v.clone())),
+                _ => None,
+            })
+            .peekable();
+
+        let log = Arc::new(Mutex::new(IoLog::default()));
+        let mut com = prog.clone();
+        com.enable_recording(log.clone());
+        for step in 0..=steps {
+            while let Some((_, addr, v)) = sets.next_if(|s| s.0 == step) {
+                com.set(addr, v);
+            }
+            if step == steps {
+                break;
+            }
+            let input = if com.wants_input() {
+                inputs.pop_front()
+            } else {
+                None
+            };
+            match com.step(input) {
+                Ok((true, _)) | Err(ExecutionError::MissingInput { .. }) => break,
+                Ok(_) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        drop(com);
+
+        let actual = lock(&log).history(0);
+        let index =
+            (0..expected.len().max(actual.len())).find(|i| expected.get(*i) != actual.get(*i));
+        Ok(index.map(|index| Divergence {
+            machine: id,
+            index,
+            expected: expected.get(index).cloned(),
+            actual: actual.get(index).cloned(),
+        }))
+    }
+
+    /// Writes one `machine <id> <steps> [<parent> <inherited events>]` line per machine,
+    /// each followed by its events.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for (id, log) in self.machines.iter() {
+            write!(w, "machine {} {}", id, log.steps)?;
+            if let Some((parent, inherited)) = log.parent {
+                write!(w, " {} {}", parent, inherited)?;
+            }
+            writeln!(w)?;
+            for e in log.events.iter() {
+                writeln!(w, "  {}", e)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// reads a log written by `write_to`
+    pub fn parse(src: &str) -> Result<IoLog<T>, String> {
+        let mut log = IoLog::default();
+        let mut current = None;
+        for (n, line) in src.lines().enumerate() {
+            let err = |msg: &str| format!("line {}: {}", n + 1, msg);
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let num = |i: usize| -> Result<u64, String> {
+                words
+                    .get(i)
+                    .and_then(|w| w.parse().ok())
+                    .ok_or_else(|| err("expecting a number"))
+            };
+            let value = |i: usize| -> Result<T, String> {
+                words
+                    .get(i)
+                    .and_then(|w| w.parse().ok())
+                    .ok_or_else(|| err("expecting a value"))
+            };
+
+            let event = match words.as_slice() {
+                [] => continue,
+                ["machine", ..] => {
+                    let parent = match words.len() {
+                        3 => None,
+                        5 => Some((num(3)? as usize, num(4)? as usize)),
+                        _ => return Err(err("malformed machine line")),
+                    };
+                    let id = num(1)? as usize;
+                    // clones start recording after the machine they come from
+                    if parent.is_some_and(|(p, _)| p >= id || !log.machines.contains_key(&p)) {
+                        return Err(err("parent machine must come before its clone"));
+                    }
+                    log.machines.insert(
+                        id,
+                        MachineLog {
+                            parent,
+                            steps: num(2)?,
+                            events: vec![],
+                        },
+                    );
+                    current = Some(id);
+                    continue;
+                }
+                [_, "in", ..] => Io::In(value(2)?),
+                [_, "out", ..] => Io::Out(value(2)?),
+                [_, "set", ..] => Io::Set(num(2)? as usize, value(3)?),
+                _ => return Err(err("unknown event")),
+            };
+            let id = current.ok_or_else(|| err("event before any machine"))?;
+            let step = num(0)?;
+            log.machines
+                .get_mut(&id)
+                .expect("current machine was inserted")
+                .events
+                .push(Event { step, io: event });
+        }
+        Ok(log)
+    }
+}
+
+/// a poisoned log still holds everything recorded before the panic
+fn lock<T>(log: &Mutex<IoLog<T>>) -> MutexGuard<'_, IoLog<T>> {
+    log.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records the I/O of one machine, added to the shared log when dropped.
+pub(super) struct Recorder<T> {
+    id: usize,
+    steps: u64,
+    /// events recorded in all, flushed or not
+    count: usize,
+    events: Vec<Event<T>>,
+    shared: Arc<Mutex<IoLog<T>>>,
+}
+
+impl<T: Cell> Recorder<T> {
+    pub(super) fn new(shared: Arc<Mutex<IoLog<T>>>) -> Recorder<T> {
+        let id = lock(&shared).add(None, 0);
+        Recorder {
+            id,
+            steps: 0,
+            count: 0,
+            events: vec![],
+            shared,
+        }
+    }
+
+    pub(super) fn record(&mut self, io: Io<T>) {
+        self.events.push(Event {
+            step: self.steps,
+            io,
+        });
+        self.count += 1;
+    }
+
+    pub(super) fn stepped(&mut self) {
+        self.steps += 1;
+    }
+}
+
+impl<T> Recorder<T> {
+    /// moves the events so far to the shared log
+    pub(super) fn flush(&mut self) {
+        let mut shared = lock(&self.shared);
+        if let Some(log) = shared.machines.get_mut(&self.id) {
+            log.steps = self.steps;
+            log.events.append(&mut self.events);
+        }
+    }
+}
+
+/// a clone records as a new machine that inherits the events so far
+impl<T> Clone for Recorder<T> {
+    fn clone(&self) -> Recorder<T> {
+        let id = lock(&self.shared).add(Some((self.id, self.count)), self.steps);
+        Recorder {
+            id,
+            steps: self.steps,
+            count: self.count,
+            events: vec![],
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Recorder<T> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode;
+
+    // day 5 example: outputs 999, 1000 or 1001 for input below, equal to or above 8
+    const CMP_8: [isize; 47] = [
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
+        1105, 1, 46, 98, 99,
+    ];
+
+    fn record(prog: &[isize]) -> IoLog {
+        let log = Arc::new(Mutex::new(IoLog::default()));
+        let mut com = intcode::new(prog.to_vec());
+        com.enable_recording(log.clone());
+        for v in [7, 8] {
+            let mut clone = com.clone();
+            clone
+                .run(&mut VecDeque::from(vec![v]), &mut vec![])
+                .unwrap();
+        }
+        com.set(21, 9);
+        drop(com);
+        Arc::try_unwrap(log).unwrap().into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_record() {
+        let log = record(&CMP_8);
+        assert_eq!(log.machines.len(), 3);
+        assert_eq!(log.machines[&1].parent, Some((0, 0)));
+        let history = log.history(2);
+        assert_eq!(
+            history[0],
+            Event {
+                step: 0,
+                io: Io::In(8)
+            }
+        );
+        assert!(matches!(history[1].io, Io::Out(1000)));
+        assert_eq!(
+            log.history(0),
+            vec![Event {
+                step: 0,
+                io: Io::Set(21, 9)
+            }]
+        );
+
+        let mut text = vec![];
+        log.write_to(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("machine 0 0\n  0 set 21 9\nmachine 1 "));
+        assert_eq!(IoLog::parse(&text), Ok(log));
+    }
+
+    #[test]
+    fn test_replay() {
+        let log = record(&CMP_8);
+        let prog = intcode::new(CMP_8.to_vec());
+        assert_eq!(log.replay(&prog), Ok(None));
+
+        // answering 2000 instead of 1000 on an input of exactly 8
+        let mut changed = CMP_8.to_vec();
+        changed[24] = 250;
+        let d = log.replay(&intcode::new(changed)).unwrap().unwrap();
+        assert_eq!((d.machine, d.index), (2, 1));
+        assert_eq!(d.expected.unwrap().io, Io::Out(1000));
+        assert_eq!(d.actual.unwrap().io, Io::Out(2000));
+
+        assert!(IoLog::<isize>::parse("  0 in 5").is_err());
+        assert!(IoLog::<isize>::parse("machine 0 1\n  0 jump 5").is_err());
+        // its own parent, or one that is not there
+        assert_eq!(
+            IoLog::<isize>::parse("machine 0 1 0 1").err(),
+            Some("line 1: parent machine must come before its clone".to_string())
+        );
+        assert!(IoLog::<isize>::parse("machine 0 1\nmachine 2 1 1 0").is_err());
+    }
+}
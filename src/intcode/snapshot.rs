@@ -0,0 +1,539 @@
+use std::{collections::VecDeque, error::Error, fmt, fs, io, path::Path, sync::Arc};
+
+use super::{
+    cache::OpCache,
+    dialect::Dialect,
+    memory::{Memory, Store},
+    IntcodeComputer,
+};
+
+const MAGIC: &[u8; 4] = b"ICS1";
+
+/// no address in a snapshot goes beyond this, far more memory than any
+/// program needs but far from overflowing
+const MAX_ADDRESS: isize = 1 << 48;
+
+/// A paused machine together with the input it has not consumed yet and the
+/// output it produced so far. Tracing state is not part of a snapshot.
+pub struct Snapshot {
+    pub machine: IntcodeComputer,
+    pub inputs: VecDeque<isize>,
+    pub outputs: Vec<isize>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot io error: {}", e),
+            SnapshotError::Format(msg) => write!(f, "malformed snapshot: {}", msg),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> SnapshotError {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<String> for SnapshotError {
+    fn from(msg: String) -> SnapshotError {
+        SnapshotError::Format(msg)
+    }
+}
+
+impl From<&str> for SnapshotError {
+    fn from(msg: &str) -> SnapshotError {
+        SnapshotError::Format(msg.to_string())
+    }
+}
+
+impl Snapshot {
+    pub fn new(machine: IntcodeComputer) -> Snapshot {
+        Snapshot {
+            machine,
+            inputs: VecDeque::new(),
+            outputs: vec![],
+        }
+    }
+
+    /// writes JSON if `path` ends in `.json`, the binary format otherwise
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|e| e == "json") {
+            fs::write(path, self.to_json()?)?;
+        } else {
+            fs::write(path, self.to_bytes()?)?;
+        }
+        Ok(())
+    }
+
+    /// reads either format, telling them apart by the binary magic bytes
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, SnapshotError> {
+        let data = fs::read(path)?;
+        if data.starts_with(MAGIC) {
+            Snapshot::from_bytes(&data)
+        } else {
+            let text = String::from_utf8(data).map_err(|e| e.to_string())?;
+            Snapshot::from_json(&text)
+        }
+    }
+
+    /// Fails on a machine that ran past `MAX_ADDRESS`, as loading the
+    /// snapshot would.
+    fn check(&self) -> Result<(), SnapshotError> {
+        let (pos, extent) = (self.machine.pos, self.machine.mem.extent());
+        if pos > MAX_ADDRESS as usize || extent > MAX_ADDRESS as usize {
+            return Err(format!("invalid pos {} or extent {}", pos, extent).into());
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        self.check()?;
+        let list = |v: &mut dyn Iterator<Item = &isize>| {
+            v.map(|x| x.to_string()).collect::<Vec<_>>().join(",")
+        };
+        let segments = self
+            .machine
+            .mem
+            .segments()
+            .iter()
+            .map(|(start, cells)| format!("[{},[{}]]", start, list(&mut cells.iter())))
+            .collect::<Vec<_>>()
+            .join(",\n    ");
+
+        Ok(format!(
+            "{{\n  \"pos\": {},\n  \"relative_base\": {},\n  \"extent\": {},\n  \"inputs\": [{}],\n  \"outputs\": [{}],\n  \"memory\": [\n    {}\n  ]\n}}\n",
+            self.machine.pos,
+            self.machine.relative_base,
+            self.machine.mem.extent(),
+            list(&mut self.inputs.iter()),
+            list(&mut self.outputs.iter()),
+            segments
+        ))
+    }
+
+    pub fn from_json(s: &str) -> Result<Snapshot, SnapshotError> {
+        let mut parser = json::Parser::new(s);
+        let value = parser.parse()?;
+        parser.end()?;
+
+        let int = |key: &str| value.get(key).and_then(|v| v.as_int());
+        let list = |key: &str| -> Result<Vec<isize>, String> {
+            value
+                .get(key)
+                .and_then(|v| v.as_ints())
+                .ok_or_else(|| format!("missing or invalid {}", key))
+        };
+        let segments = value
+            .get("memory")
+            .and_then(|v| v.as_array())
+            .ok_or("missing or invalid memory")?
+            .iter()
+            .map(|seg| match seg.as_array() {
+                Some([start, cells]) => Some((start.as_int()?, cells.as_ints()?)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or("invalid memory segment")?;
+
+        let pos = int("pos").ok_or("missing or invalid pos")?;
+        let extent = int("extent").ok_or("missing or invalid extent")?;
+        Ok(Snapshot {
+            machine: restore(
+                pos,
+                int("relative_base").ok_or("missing or invalid relative_base")?,
+                &segments,
+                extent,
+            )?,
+            inputs: list("inputs")?.into(),
+            outputs: list("outputs")?,
+        })
+    }
+
+    /// `ICS1` followed by zigzag LEB128 varints: pos, relative base, extent,
+    /// then the inputs, outputs and memory segments each prefixed by a count
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        self.check()?;
+        let mut out = MAGIC.to_vec();
+        let m = &self.machine;
+        for v in [m.pos as isize, m.relative_base, m.mem.extent() as isize].iter() {
+            put(&mut out, *v);
+        }
+        put_list(&mut out, self.inputs.iter());
+        put_list(&mut out, self.outputs.iter());
+
+        let segments = m.mem.segments();
+        put(&mut out, segments.len() as isize);
+        for (start, cells) in segments.iter() {
+            put(&mut out, *start as isize);
+            put_list(&mut out, cells.iter());
+        }
+        Ok(out)
+    }
+
+    pub fn from_bytes(b: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let mut r = b.strip_prefix(MAGIC).ok_or("missing magic bytes")?;
+        let pos = take(&mut r)?;
+        let relative_base = take(&mut r)?;
+        let extent = take(&mut r)?;
+        let inputs = take_list(&mut r)?;
+        let outputs = take_list(&mut r)?;
+        let segments = (0..take(&mut r)?)
+            .map(|_| Ok((take(&mut r)?, take_list(&mut r)?)))
+            .collect::<Result<Vec<_>, SnapshotError>>()?;
+        if !r.is_empty() {
+            return Err(format!("{} trailing bytes", r.len()).into());
+        }
+
+        Ok(Snapshot {
+            machine: restore(pos, relative_base, &segments, extent)?,
+            inputs: inputs.into(),
+            outputs,
+        })
+    }
+}
+
+fn restore(
+    pos: isize,
+    relative_base: isize,
+    segments: &[(isize, Vec<isize>)],
+    extent: isize,
+) -> Result<IntcodeComputer, SnapshotError> {
+    let valid = |addr: isize| (0..=MAX_ADDRESS).contains(&addr);
+    if !valid(pos) || !valid(extent) {
+        return Err(format!("invalid pos {} or extent {}", pos, extent).into());
+    }
+    let segments = segments
+        .iter()
+        .map(|(start, cells)| {
+            let end = start.checked_add(cells.len() as isize);
+            if valid(*start) && end.is_some_and(valid) {
+                Ok((*start as usize, cells.clone()))
+            } else {
+                Err(format!("invalid memory segment at {}", start))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mem = Memory::from_segments(&segments, extent as usize);
+    let dialect = Dialect::standard();
+    Ok(IntcodeComputer {
+        cache: Some(OpCache::translate(&mem, &dialect)),
+        dialect: Arc::new(dialect),
+        mem,
+        pos: pos as usize,
+        relative_base,
+        checked: false,
+        tracer: None,
+        history: None,
+        guard: None,
+        profiler: None,
+        recorder: None,
+    })
+}
+
+fn put(out: &mut Vec<u8>, v: isize) {
+    let mut z = ((v << 1) ^ (v >> (isize::BITS - 1))) as usize;
+    loop {
+        let byte = (z & 0x7f) as u8;
+        z >>= 7;
+        if z == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn put_list<'a, I: ExactSizeIterator<Item = &'a isize>>(out: &mut Vec<u8>, values: I) {
+    put(out, values.len() as isize);
+    values.for_each(|v| put(out, *v));
+}
+
+fn take(r: &mut &[u8]) -> Result<isize, SnapshotError> {
+    let mut z: usize = 0;
+    let mut shift = 0;
+    loop {
+        let (byte, rest) = r.split_first().ok_or("unexpected end of data")?;
+        *r = rest;
+        if shift >= usize::BITS {
+            return Err("varint too long".into());
+        }
+        z |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(((z >> 1) as isize) ^ -((z & 1) as isize));
+        }
+    }
+}
+
+fn take_list(r: &mut &[u8]) -> Result<Vec<isize>, SnapshotError> {
+    let n = take(r)?;
+    if n < 0 || n as usize > r.len() {
+        return Err(format!("invalid list length {}", n).into());
+    }
+    (0..n).map(|_| take(r)).collect()
+}
+
+/// just enough JSON to read snapshots back: objects, arrays and integers
+mod json {
+    pub enum Value {
+        Int(isize),
+        Array(Vec<Value>),
+        Object(Vec<(String, Value)>),
+    }
+
+    impl Value {
+        pub fn get(&self, key: &str) -> Option<&Value> {
+            match self {
+                Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+                _ => None,
+            }
+        }
+
+        pub fn as_int(&self) -> Option<isize> {
+            match self {
+                Value::Int(v) => Some(*v),
+                _ => None,
+            }
+        }
+
+        pub fn as_array(&self) -> Option<&[Value]> {
+            match self {
+                Value::Array(v) => Some(v),
+                _ => None,
+            }
+        }
+
+        pub fn as_ints(&self) -> Option<Vec<isize>> {
+            self.as_array()?.iter().map(|v| v.as_int()).collect()
+        }
+    }
+
+    pub struct Parser<'a> {
+        s: &'a str,
+        pos: usize,
+    }
+
+    impl<'a> Parser<'a> {
+        pub fn new(s: &'a str) -> Parser<'a> {
+            Parser { s, pos: 0 }
+        }
+
+        pub fn parse(&mut self) -> Result<Value, String> {
+            match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    let mut fields = vec![];
+                    if !self.eat('}') {
+                        loop {
+                            let key = self.string()?;
+                            self.expect(':')?;
+                            fields.push((key, self.parse()?));
+                            if self.eat('}') {
+                                break;
+                            }
+                            self.expect(',')?;
+                        }
+                    }
+                    Ok(Value::Object(fields))
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    let mut items = vec![];
+                    if !self.eat(']') {
+                        loop {
+                            items.push(self.parse()?);
+                            if self.eat(']') {
+                                break;
+                            }
+                            self.expect(',')?;
+                        }
+                    }
+                    Ok(Value::Array(items))
+                }
+                Some(c) if c == '-' || c.is_ascii_digit() => {
+                    let start = self.pos;
+                    self.pos += 1;
+                    while self.s[self.pos..].starts_with(|c: char| c.is_ascii_digit()) {
+                        self.pos += 1;
+                    }
+                    let num = &self.s[start..self.pos];
+                    num.parse()
+                        .map(Value::Int)
+                        .map_err(|e| format!("invalid number {}: {}", num, e))
+                }
+                c => Err(format!("unexpected {:?} at {}", c, self.pos)),
+            }
+        }
+
+        /// fails if anything but whitespace is left
+        pub fn end(&mut self) -> Result<(), String> {
+            match self.peek() {
+                None => Ok(()),
+                Some(c) => Err(format!("unexpected {:?} at {}", c, self.pos)),
+            }
+        }
+
+        fn string(&mut self) -> Result<String, String> {
+            self.expect('"')?;
+            let len = self.s[self.pos..].find('"').ok_or("unterminated string")?;
+            let s = self.s[self.pos..self.pos + len].to_string();
+            self.pos += len + 1;
+            Ok(s)
+        }
+
+        fn peek(&mut self) -> Option<char> {
+            let rest = &self.s[self.pos..];
+            self.pos += rest.len() - rest.trim_start().len();
+            self.s[self.pos..].chars().next()
+        }
+
+        fn eat(&mut self, c: char) -> bool {
+            if self.peek() == Some(c) {
+                self.pos += 1;
+                return true;
+            }
+            false
+        }
+
+        fn expect(&mut self, c: char) -> Result<(), String> {
+            if self.eat(c) {
+                return Ok(());
+            }
+            Err(format!("expected {:?} at {}", c, self.pos))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, StopEvent};
+
+    fn paused() -> Snapshot {
+        // reads a value, writes it far out, outputs it, then waits for more input
+        let mut com = intcode::new(vec![3, 100, 1001, 100, 0, 100_000, 4, 100_000, 3, 0, 99]);
+        let mut inputs = VecDeque::from(vec![-42]);
+        let mut outputs = vec![];
+        let event = com.run(&mut inputs, &mut outputs).unwrap();
+        assert_eq!(event, StopEvent::WaitingOnInput);
+
+        inputs.push_back(7);
+        Snapshot {
+            machine: com,
+            inputs,
+            outputs,
+        }
+    }
+
+    fn assert_same(a: &Snapshot, b: &Snapshot) {
+        assert_eq!(a.machine.pos, b.machine.pos);
+        assert_eq!(a.machine.relative_base, b.machine.relative_base);
+        assert_eq!(a.machine.mem.segments(), b.machine.mem.segments());
+        assert_eq!(a.machine.mem.extent(), b.machine.mem.extent());
+        assert_eq!(a.inputs, b.inputs);
+        assert_eq!(a.outputs, b.outputs);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let snap = paused();
+        let json = snap.to_json().unwrap();
+        assert!(json.contains("\"outputs\": [-42]"));
+        assert_same(&snap, &Snapshot::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let snap = paused();
+        let bytes = snap.to_bytes().unwrap();
+        assert!(bytes.len() < 2 * 512 + 32);
+        let mut restored = Snapshot::from_bytes(&bytes).unwrap();
+        assert_same(&snap, &restored);
+
+        // the restored machine carries on where the original stopped
+        let event = restored
+            .machine
+            .run(&mut restored.inputs, &mut restored.outputs)
+            .unwrap();
+        assert_eq!(event, StopEvent::Finished);
+        assert_eq!(restored.machine.peek(0), 7);
+    }
+
+    #[test]
+    fn test_rejects_garbage() {
+        assert!(Snapshot::from_bytes(b"ICS1\x80").is_err());
+        assert!(Snapshot::from_bytes(b"nope").is_err());
+        assert!(Snapshot::from_json("{\"pos\": 1}").is_err());
+        assert!(Snapshot::from_json("[1, 2").is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_addresses() {
+        let json = |memory: &str, extent: isize| {
+            format!(
+                "{{\"pos\": 0, \"relative_base\": 0, \"inputs\": [], \"outputs\": [], \
+                 \"extent\": {}, \"memory\": {}}}",
+                extent, memory
+            )
+        };
+        assert!(Snapshot::from_json(&json("[[0, [1, 2]]]", 5)).is_ok());
+        for bad in [
+            json("[[-1, [1, 2]]]", 5),
+            json(&format!("[[{}, [1, 2]]]", isize::MAX), 5),
+            json(&format!("[[{}, [1, 2]]]", MAX_ADDRESS - 1), 5),
+            json("[[0, [1, 2]]]", isize::MAX),
+        ]
+        .iter()
+        {
+            assert!(Snapshot::from_json(bad).is_err(), "{}", bad);
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        [0, 0, 5, 0, 0, 1, -1, 1, 7]
+            .iter()
+            .for_each(|v| put(&mut bytes, *v));
+        assert!(Snapshot::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_saves_only_what_loads() {
+        let mut snap = paused();
+        snap.machine.set(MAX_ADDRESS as usize - 1, 1);
+        let json = snap.to_json().unwrap();
+        assert_same(&snap, &Snapshot::from_json(&json).unwrap());
+
+        snap.machine.set(MAX_ADDRESS as usize, 1);
+        assert!(snap.to_json().is_err());
+        assert!(snap.to_bytes().is_err());
+        let path = std::env::temp_dir().join(format!("far-{}.snap", std::process::id()));
+        assert!(snap.save(&path).is_err());
+        assert!(!path.exists());
+
+        let mut snap = paused();
+        snap.machine.pos = MAX_ADDRESS as usize + 1;
+        assert!(snap.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_varints() {
+        let values = [0, 1, -1, 63, -64, 64, isize::MAX, isize::MIN];
+        let mut buf = vec![];
+        values.iter().for_each(|v| put(&mut buf, *v));
+        let mut r = &buf[..];
+        for v in values.iter() {
+            assert_eq!(take(&mut r).unwrap(), *v);
+        }
+        assert!(r.is_empty());
+    }
+}
@@ -0,0 +1,649 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt,
+    ops::Range,
+    rc::Rc,
+    time::Instant,
+};
+
+use super::{
+    dialect::{Dialect, OpDef},
+    limits::{Limits, CLOCK_EVERY},
+    memory::{Memory, Store},
+    modes::OpMode,
+    opcode::Opcode,
+};
+
+/// expressions that are not linear may grow to this many nodes, so that
+/// printing or dropping one stays cheap
+const MAX_SIZE: usize = 1 << 10;
+
+/// A value computed from the symbolic cells a program was started with.
+/// Sums of those cells times constants are folded into one `Sum` as they
+/// are built, so a long chain of additions stays a single node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Const(isize),
+    /// a linear function of the cells, depending on at least one of them
+    Sum(Linear),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    Lt(Rc<Expr>, Rc<Expr>),
+    Eq(Rc<Expr>, Rc<Expr>),
+    /// whatever was in the cell at a symbolic address when it was read
+    Load(Rc<Expr>),
+}
+
+impl Expr {
+    /// what the cell at `addr` held when the program started
+    pub fn var(addr: usize) -> Rc<Expr> {
+        Rc::new(Expr::Sum(Linear {
+            coefs: vec![(addr, 1)].into_iter().collect(),
+            constant: 0,
+        }))
+    }
+
+    pub fn constant(&self) -> Option<isize> {
+        match self {
+            Expr::Const(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// A constant once no cell counts any more. Constants out of range
+    /// wrap, like they do on an unchecked machine.
+    fn from_linear(l: Linear) -> Rc<Expr> {
+        if l.coefs.is_empty() {
+            Rc::new(Expr::Const(l.constant as isize))
+        } else {
+            Rc::new(Expr::Sum(l))
+        }
+    }
+
+    /// Builds `a + b`, folding constants and sums. Constants add with
+    /// wrapping, like an unchecked machine does.
+    pub fn add(a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => return Rc::new(Expr::Const(x.wrapping_add(y))),
+            (Some(0), _) => return b,
+            (_, Some(0)) => return a,
+            _ => {}
+        }
+        match (a.linear(), b.linear()) {
+            (Some(x), Some(y)) => match x.plus(&y) {
+                Some(l) => Expr::from_linear(l),
+                None => Rc::new(Expr::Add(a, b)),
+            },
+            _ => Rc::new(Expr::Add(a, b)),
+        }
+    }
+
+    /// builds `a * b`, folding constants, sums times a constant and
+    /// multiplications by 0 and 1
+    pub fn mul(a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+        let scaled = match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => return Rc::new(Expr::Const(x.wrapping_mul(y))),
+            (Some(0), _) | (_, Some(0)) => return Rc::new(Expr::Const(0)),
+            (Some(1), _) => return b,
+            (_, Some(1)) => return a,
+            (Some(x), None) => b.linear().and_then(|l| l.scale(x as i128)),
+            (None, Some(y)) => a.linear().and_then(|l| l.scale(y as i128)),
+            (None, None) => None,
+        };
+        match scaled {
+            Some(l) => Expr::from_linear(l),
+            None => Rc::new(Expr::Mul(a, b)),
+        }
+    }
+
+    fn compare(a: Rc<Expr>, b: Rc<Expr>, less: bool) -> Rc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => {
+                let holds = if less { x < y } else { x == y };
+                Rc::new(Expr::Const(holds as isize))
+            }
+            _ if less => Rc::new(Expr::Lt(a, b)),
+            _ => Rc::new(Expr::Eq(a, b)),
+        }
+    }
+
+    /// The expression as a sum of variables times constants, if it is one.
+    /// Comparisons, products of two variables and loads are not.
+    pub fn linear(&self) -> Option<Linear> {
+        match self {
+            Expr::Const(v) => Some(Linear {
+                coefs: BTreeMap::new(),
+                constant: *v as i128,
+            }),
+            Expr::Sum(l) => Some(l.clone()),
+            _ => None,
+        }
+    }
+
+    /// The number of nodes, counting shared ones every time they appear,
+    /// or `None` if there are more than `limit`. Stops looking once past
+    /// `limit`, so this costs at most that many steps.
+    fn size(&self, limit: usize) -> Option<usize> {
+        let rest = limit.checked_sub(1)?;
+        match self {
+            Expr::Const(_) | Expr::Sum(_) => Some(1),
+            Expr::Add(a, b) | Expr::Mul(a, b) | Expr::Lt(a, b) | Expr::Eq(a, b) => {
+                let left = a.size(rest)?;
+                Some(1 + left + b.size(rest - left)?)
+            }
+            Expr::Load(a) => Some(1 + a.size(rest)?),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(v) => write!(f, "{}", v),
+            Expr::Sum(l) => write!(f, "{}", l),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "{} * {}", a, b),
+            Expr::Load(addr) => write!(f, "mem[{}]", addr),
+            Expr::Lt(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Eq(a, b) => write!(f, "({} == {})", a, b),
+        }
+    }
+}
+
+/// `constant + sum of coef * [addr]`, without overflow for any sensible
+/// program: coefficients are kept in 128 bits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Linear {
+    pub coefs: BTreeMap<usize, i128>,
+    pub constant: i128,
+}
+
+impl Linear {
+    /// `self + other`, or `None` if that does not fit
+    fn plus(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = self.clone();
+        for (var, c) in other.coefs.iter() {
+            let coef = sum.coefs.entry(*var).or_insert(0);
+            *coef = coef.checked_add(*c)?;
+        }
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        sum.coefs.retain(|_, c| *c != 0);
+        Some(sum)
+    }
+
+    /// `self * by`, or `None` if that does not fit
+    fn scale(&self, by: i128) -> Option<Linear> {
+        let mut l = self.clone();
+        for c in l.coefs.values_mut() {
+            *c = c.checked_mul(by)?;
+        }
+        l.constant = l.constant.checked_mul(by)?;
+        l.coefs.retain(|_, c| *c != 0);
+        Some(l)
+    }
+
+    /// Values for `vars`, each taken from `range`, that make the sum equal
+    /// `target`, or `None` if there are none. All but the last variable
+    /// with a coefficient are tried in turn, skipping those that leave the
+    /// rest out of reach, and the last one is solved for.
+    pub fn solve(&self, vars: &[usize], range: Range<isize>, target: isize) -> Option<Vec<isize>> {
+        if range.is_empty() {
+            return None;
+        }
+        let terms = vars
+            .iter()
+            .filter_map(|v| self.coefs.get(v).map(|c| (*v, *c)))
+            .collect::<Vec<_>>();
+        if terms.len() < self.coefs.len() {
+            // the sum depends on a cell we may not pick
+            return None;
+        }
+        let (lo, hi) = (range.start as i128, range.end as i128 - 1);
+        let mut picked = HashMap::new();
+        let rest = (target as i128).checked_sub(self.constant)?;
+        if !pick(&terms, lo, hi, rest, &mut picked) {
+            return None;
+        }
+        // variables that do not count get the first value there is
+        Some(
+            vars.iter()
+                .map(|v| picked.get(v).copied().unwrap_or(range.start))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms = self
+            .coefs
+            .iter()
+            .map(|(addr, c)| match c {
+                1 => format!("[{}]", addr),
+                _ => format!("{} * [{}]", c, addr),
+            })
+            .collect::<Vec<_>>();
+        if self.constant != 0 || terms.is_empty() {
+            terms.push(self.constant.to_string());
+        }
+        match terms.len() {
+            1 => write!(f, "{}", terms[0]),
+            _ => write!(f, "({})", terms.join(" + ")),
+        }
+    }
+}
+
+/// Picks values in `lo..=hi` for `terms` adding up to `rest`. Sums that
+/// do not fit in 128 bits cannot be reached.
+fn pick(
+    terms: &[(usize, i128)],
+    lo: i128,
+    hi: i128,
+    rest: i128,
+    picked: &mut HashMap<usize, isize>,
+) -> bool {
+    let ((var, c), others) = match terms.split_last() {
+        Some(t) => t,
+        None => return rest == 0,
+    };
+    if others.is_empty() {
+        return rest.checked_rem(*c) == Some(0) && (lo..=hi).contains(&(rest / c)) && {
+            picked.insert(*var, (rest / c) as isize);
+            true
+        };
+    }
+    // what the other terms can add up to at most and at least
+    let bounds = others
+        .iter()
+        .try_fold((0i128, 0i128), |(min, max), (_, c)| {
+            let (a, b) = (c.checked_mul(lo)?, c.checked_mul(hi)?);
+            Some((min.checked_add(a.min(b))?, max.checked_add(a.max(b))?))
+        });
+    let (min, max) = match bounds {
+        Some(b) => b,
+        None => return false,
+    };
+    for v in lo..=hi {
+        let left = match c.checked_mul(v).and_then(|p| rest.checked_sub(p)) {
+            Some(left) => left,
+            None => continue,
+        };
+        if left >= min && left <= max && pick(others, lo, hi, left, picked) {
+            picked.insert(*var, v as isize);
+            return true;
+        }
+    }
+    false
+}
+
+/// why a program could not be run symbolically
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unsupported {
+    /// the instruction at `addr` depends on a symbolic value
+    SymbolicOpcode {
+        addr: usize,
+    },
+    /// the instruction at `addr` writes at a symbolic address, or moves
+    /// the relative base by a symbolic amount
+    SymbolicAddress {
+        addr: usize,
+    },
+    /// the jump at `addr` depends on a symbolic value
+    SymbolicBranch {
+        addr: usize,
+    },
+    /// the instruction at `addr` asks for input
+    Input {
+        addr: usize,
+    },
+    /// the instruction at `addr` could not run at all
+    Fault {
+        addr: usize,
+    },
+    /// the dialect defines the instruction at `addr` some other way than
+    /// the standard one
+    NonStandard {
+        addr: usize,
+    },
+    /// the value computed at `addr` is neither linear nor small, see `MAX_SIZE`
+    TooComplex {
+        addr: usize,
+    },
+    StepLimit,
+    Timeout,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsupported::SymbolicOpcode { addr } => write!(f, "symbolic instruction at {}", addr),
+            Unsupported::SymbolicAddress { addr } => {
+                write!(f, "symbolic address used at {}", addr)
+            }
+            Unsupported::SymbolicBranch { addr } => write!(f, "symbolic branch at {}", addr),
+            Unsupported::Input { addr } => write!(f, "input read at {}", addr),
+            Unsupported::Fault { addr } => write!(f, "fault at {}", addr),
+            Unsupported::NonStandard { addr } => {
+                write!(f, "non-standard instruction at {}", addr)
+            }
+            Unsupported::TooComplex { addr } => write!(f, "value too complex at {}", addr),
+            Unsupported::StepLimit => write!(f, "step limit reached"),
+            Unsupported::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+/// What a program computes from its symbolic cells once it halted.
+pub struct Outcome {
+    memory: BTreeMap<usize, Rc<Expr>>,
+    pub outputs: Vec<Rc<Expr>>,
+}
+
+impl Outcome {
+    /// what the cell at `addr` holds
+    pub fn cell(&self, addr: usize) -> Rc<Expr> {
+        self.memory
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| Rc::new(Expr::Const(0)))
+    }
+}
+
+/// Runs the program in `segments`, see `Store::segments`, with the cells
+/// at `vars` left unknown, as a machine with
+/// `dialect` would, within the step budget and timeout of `limits`. Only
+/// works as long as the path through the program, and every address it
+/// touches, does not depend on those cells.
+pub fn run<M: Store<isize>>(
+    segments: &[(usize, Vec<isize>)],
+    vars: &[usize],
+    dialect: &Dialect<isize, M>,
+    limits: &Limits,
+) -> Result<Outcome, Unsupported> {
+    let mut m = Machine {
+        mem: segments
+            .iter()
+            .flat_map(|(start, cells)| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, v)| (start + i, Rc::new(Expr::Const(*v))))
+            })
+            .collect(),
+        pc: 0,
+        base: 0,
+        dialect,
+    };
+    for v in vars {
+        m.store(*v, Expr::var(*v));
+    }
+    let start = Instant::now();
+    let mut outputs = vec![];
+    let mut steps = 0;
+    while m.step(&mut outputs)? {
+        steps += 1;
+        if limits.max_steps.is_some_and(|max| steps >= max) {
+            return Err(Unsupported::StepLimit);
+        }
+        let timeout = limits.timeout.filter(|_| steps.is_multiple_of(CLOCK_EVERY));
+        if timeout.is_some_and(|t| start.elapsed() >= t) {
+            return Err(Unsupported::Timeout);
+        }
+    }
+    Ok(Outcome {
+        memory: m.mem,
+        outputs,
+    })
+}
+
+struct Machine<'a, M = Memory<isize>> {
+    /// only the cells loaded or written, as a program may write far out
+    mem: BTreeMap<usize, Rc<Expr>>,
+    pc: usize,
+    base: isize,
+    dialect: &'a Dialect<isize, M>,
+}
+
+impl<M: Store<isize>> Machine<'_, M> {
+    fn load(&self, addr: usize) -> Rc<Expr> {
+        self.mem
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| Rc::new(Expr::Const(0)))
+    }
+
+    fn store(&mut self, addr: usize, v: Rc<Expr>) {
+        self.mem.insert(addr, v);
+    }
+
+    /// runs one instruction, returning false once the program halted
+    fn step(&mut self, outputs: &mut Vec<Rc<Expr>>) -> Result<bool, Unsupported> {
+        let at = self.pc;
+        let fault = Unsupported::Fault { addr: at };
+        let cell = self
+            .load(at)
+            .constant()
+            .ok_or(Unsupported::SymbolicOpcode { addr: at })?;
+        let code = cell % 100;
+        let def = self.dialect.get(code).ok_or(fault)?;
+        let standard = Opcode::from_code(code).map(OpDef::<isize, M>::standard);
+        let same = |s: &OpDef<isize, M>| {
+            (s.mnemonic, s.arity, s.writes, s.input)
+                == (def.mnemonic, def.arity, def.writes, def.input)
+        };
+        if !standard.as_ref().is_some_and(same) {
+            return Err(Unsupported::NonStandard { addr: at });
+        }
+        let d = self.dialect.decode(cell).map_err(|_| fault)?;
+        let small = |e: Rc<Expr>| match e.size(MAX_SIZE) {
+            Some(_) => Ok(e),
+            None => Err(Unsupported::TooComplex { addr: at }),
+        };
+
+        // where parameter `i` points, in position or relative mode
+        let address = |m: &Machine<M>, i: usize| -> Result<Rc<Expr>, Unsupported> {
+            let raw = m.load(at + 1 + i);
+            match (d.mode(i), raw.constant()) {
+                (OpMode::Relative, Some(v)) => {
+                    let addr = v.checked_add(m.base).ok_or(fault)?;
+                    Ok(Rc::new(Expr::Const(addr)))
+                }
+                (OpMode::Relative, None) => Ok(Expr::add(raw, Rc::new(Expr::Const(m.base)))),
+                _ => Ok(raw),
+            }
+        };
+        let value = |m: &Machine<M>, i: usize| -> Result<Rc<Expr>, Unsupported> {
+            if d.mode(i) == OpMode::Immediate {
+                return Ok(m.load(at + 1 + i));
+            }
+            // reading at an unknown address is fine, the value is just
+            // unknown too
+            let addr = address(m, i)?;
+            match addr.constant() {
+                Some(a) => Ok(m.load(usize::try_from(a).map_err(|_| fault)?)),
+                None => Ok(Rc::new(Expr::Load(addr))),
+            }
+        };
+        let concrete = |e: Rc<Expr>| e.constant().ok_or(Unsupported::SymbolicBranch { addr: at });
+
+        let op = d.op().ok_or(fault)?;
+        let result = match op {
+            Opcode::Add => small(Expr::add(value(self, 0)?, value(self, 1)?))?,
+            Opcode::Mul => small(Expr::mul(value(self, 0)?, value(self, 1)?))?,
+            Opcode::Lt => small(Expr::compare(value(self, 0)?, value(self, 1)?, true))?,
+            Opcode::Eq => small(Expr::compare(value(self, 0)?, value(self, 1)?, false))?,
+            Opcode::In => return Err(Unsupported::Input { addr: at }),
+            Opcode::Out => {
+                outputs.push(small(value(self, 0)?)?);
+                self.pc += d.size();
+                return Ok(true);
+            }
+            Opcode::Jnz | Opcode::Jz => {
+                let cond = concrete(value(self, 0)?)?;
+                if (cond != 0) == (op == Opcode::Jnz) {
+                    let to = concrete(value(self, 1)?)?;
+                    self.pc = usize::try_from(to).map_err(|_| fault)?;
+                } else {
+                    self.pc += d.size();
+                }
+                return Ok(true);
+            }
+            Opcode::Arb => {
+                let by = value(self, 0)?
+                    .constant()
+                    .ok_or(Unsupported::SymbolicAddress { addr: at })?;
+                self.base = self.base.checked_add(by).ok_or(fault)?;
+                self.pc += d.size();
+                return Ok(true);
+            }
+            Opcode::Hlt => return Ok(false),
+        };
+        let to = address(self, d.size() - 2)?
+            .constant()
+            .ok_or(Unsupported::SymbolicAddress { addr: at })?;
+        self.store(usize::try_from(to).map_err(|_| fault)?, result);
+        self.pc += d.size();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm;
+
+    /// runs `prog` on the standard dialect for at most `max_steps` steps
+    fn steps(prog: &[isize], vars: &[usize], max_steps: u64) -> Result<Outcome, Unsupported> {
+        let limits = Limits {
+            max_steps: Some(max_steps),
+            ..Limits::default()
+        };
+        run(
+            &[(0, prog.to_vec())],
+            vars,
+            &Dialect::<isize>::standard(),
+            &limits,
+        )
+    }
+
+    #[test]
+    fn test_linear() {
+        // [0] = ([13] + 3) * 5 + [14]
+        let prog = asm::assemble(
+            "
+                add [13], #3, [15]
+                mul [15], #5, [15]
+                add [15], [14], [0]
+                hlt
+                data 0, 0, 0
+            ",
+        )
+        .unwrap();
+        let out = steps(&prog, &[13, 14], 100).unwrap();
+        let l = out.cell(0).linear().unwrap();
+        assert_eq!(l.constant, 15);
+        assert_eq!(l.coefs, vec![(13, 5), (14, 1)].into_iter().collect());
+        let found = l.solve(&[13, 14], 0..100, 15 + 5 * 42 + 7).unwrap();
+        assert_eq!(5 * found[0] + found[1], 5 * 42 + 7);
+        assert_eq!(l.solve(&[13, 14], 0..5, 15 + 5 * 3 + 4), Some(vec![3, 4]));
+        assert_eq!(l.solve(&[13, 14], 0..10, 1000), None);
+        // [14] is the only other way to get there
+        assert_eq!(l.solve(&[13], 0..100, 20), None);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        // a symbolic product and a comparison are not linear
+        let prog = vec![2, 1, 2, 0, 99];
+        assert_eq!(steps(&prog, &[1, 2], 10).unwrap().cell(0).linear(), None);
+        let prog = vec![7, 1, 2, 0, 99];
+        assert_eq!(steps(&prog, &[1, 2], 10).unwrap().cell(0).linear(), None);
+        // [0] = mem[[1]] + mem[[2]], which the next instruction overwrites
+        let prog = vec![1, 0, 0, 0, 1, 1, 2, 0, 99];
+        let out = steps(&prog, &[1, 2], 10).unwrap();
+        assert_eq!(out.cell(0).linear().unwrap().coefs.len(), 2);
+
+        // branching on [1]
+        let prog = vec![1005, 1, 4, 99, 99];
+        assert_eq!(
+            steps(&prog, &[1], 10).err(),
+            Some(Unsupported::SymbolicBranch { addr: 0 })
+        );
+        // writing where [3] points
+        let prog = vec![1101, 1, 1, 0, 99];
+        assert_eq!(
+            steps(&prog, &[3], 10).err(),
+            Some(Unsupported::SymbolicAddress { addr: 0 })
+        );
+        let prog = vec![1105, 1, 0];
+        assert_eq!(steps(&prog, &[], 10).err(), Some(Unsupported::StepLimit));
+    }
+
+    #[test]
+    fn test_doubling() {
+        // [1] doubles 45 times, then [0] = [1] + [2]
+        let mut prog = vec![1, 0, 0, 3];
+        (0..45).for_each(|_| prog.extend(&[1, 1, 1, 1]));
+        prog.extend(&[1, 1, 2, 0, 99]);
+        let cell = steps(&prog, &[1, 2], 100).unwrap().cell(0);
+        let l = cell.linear().unwrap();
+        assert_eq!(l.coefs, vec![(1, 1 << 45), (2, 1)].into_iter().collect());
+        assert_eq!(cell.to_string(), "(35184372088832 * [1] + [2])");
+
+        // the same with a product of loads to start from, which is not
+        // linear and too big after 8 doublings
+        let mut prog = vec![2, 1, 2, 1];
+        (0..45).for_each(|_| prog.extend(&[1, 1, 1, 1]));
+        prog.push(99);
+        assert_eq!(
+            steps(&prog, &[1, 2], 100).err(),
+            Some(Unsupported::TooComplex { addr: 32 })
+        );
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // adds 1 to [16] 100000 times, counting in [17]
+        let prog = asm::assemble(
+            "
+                loop:   add [x], #1, [x]
+                        add [n], #1, [n]
+                        lt [n], #100000, [f]
+                        jnz [f], #loop
+                        hlt
+                x:      data 0
+                n:      data 0
+                f:      data 0
+            ",
+        )
+        .unwrap();
+        let out = steps(&prog, &[16], 1_000_000).unwrap();
+        assert_eq!(out.cell(16).to_string(), "([16] + 100000)");
+        assert_eq!(
+            steps(&prog, &[16], 1000).err(),
+            Some(Unsupported::StepLimit)
+        );
+    }
+
+    #[test]
+    fn test_dialect() {
+        let prog = [(0, vec![1, 1, 2, 0, 99])];
+        let day2: Dialect = Dialect::only(&[Opcode::Add, Opcode::Hlt]);
+        assert!(run(&prog, &[1, 2], &day2, &Limits::default()).is_ok());
+        let no_add: Dialect = Dialect::only(&[Opcode::Hlt]);
+        assert_eq!(
+            run(&prog, &[1, 2], &no_add, &Limits::default()).err(),
+            Some(Unsupported::Fault { addr: 0 })
+        );
+        // an add that is not one
+        let odd: Dialect = Dialect::standard().with(OpDef {
+            mnemonic: "sub",
+            ..OpDef::standard(Opcode::Add)
+        });
+        assert_eq!(
+            run(&prog, &[1, 2], &odd, &Limits::default()).err(),
+            Some(Unsupported::NonStandard { addr: 0 })
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// a single memory access and the instruction pointer that caused it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record<T = isize> {
+    pub ip: usize,
+    pub access: Access,
+    pub addr: usize,
+    pub value: T,
+}
+
+impl<T: fmt::Display> fmt::Display for Record<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} at {} by instruction at {}",
+            self.access, self.value, self.addr, self.ip
+        )
+    }
+}
+
+impl<T: fmt::Display> Record<T> {
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"ip":{},"access":"{}","addr":{},"value":{}}}"#,
+            self.ip, self.access, self.addr, self.value
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<usize>,
+    /// only trigger on this kind of access, or on any if none
+    pub on: Option<Access>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Tracer<T = isize> {
+    /// every access since tracing was enabled, unless `keep_records` is off
+    pub records: Vec<Record<T>>,
+    pub keep_records: bool,
+    watchpoints: Vec<Watchpoint>,
+    hit: Option<Record<T>>,
+}
+
+impl<T> Default for Tracer<T> {
+    fn default() -> Tracer<T> {
+        Tracer {
+            records: vec![],
+            keep_records: false,
+            watchpoints: vec![],
+            hit: None,
+        }
+    }
+}
+
+impl<T: Clone + fmt::Display> Tracer<T> {
+    pub fn new() -> Tracer<T> {
+        Tracer {
+            keep_records: true,
+            ..Tracer::default()
+        }
+    }
+
+    pub fn watch(&mut self, range: RangeInclusive<usize>, on: Option<Access>) {
+        self.watchpoints.push(Watchpoint { range, on });
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn record(&mut self, r: Record<T>) {
+        if self.hit.is_none()
+            && self
+                .watchpoints
+                .iter()
+                .any(|w| w.range.contains(&r.addr) && w.on.is_none_or(|a| a == r.access))
+        {
+            self.hit = Some(r.clone());
+        }
+        if self.keep_records {
+            self.records.push(r);
+        }
+    }
+
+    pub fn has_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    /// the first access that touched a watchpoint since the last call
+    pub fn take_hit(&mut self) -> Option<Record<T>> {
+        self.hit.take()
+    }
+
+    pub fn write_json_lines<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for r in self.records.iter() {
+            writeln!(w, "{}", r.to_json())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{self, StopEvent};
+
+    #[test]
+    fn test_records_accesses() {
+        // day 2 example
+        let mut com = intcode::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        com.enable_tracing();
+        com.accumulate_output_until_action().unwrap();
+
+        let records = &com.tracer().unwrap().records;
+        assert_eq!(
+            records[..3],
+            [
+                Record {
+                    ip: 0,
+                    access: Access::Read,
+                    addr: 9,
+                    value: 30
+                },
+                Record {
+                    ip: 0,
+                    access: Access::Read,
+                    addr: 10,
+                    value: 40
+                },
+                Record {
+                    ip: 0,
+                    access: Access::Write,
+                    addr: 3,
+                    value: 70
+                },
+            ]
+        );
+        assert_eq!(records.len(), 6);
+        assert_eq!(
+            records[5].to_json(),
+            r#"{"ip":4,"access":"write","addr":0,"value":3500}"#
+        );
+    }
+
+    #[test]
+    fn test_watchpoint_pauses() {
+        // day 9 quine, watching the flag that decides whether to loop again
+        let mut com = intcode::new(vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ]);
+        com.enable_tracing().watch(101..=101, Some(Access::Write));
+
+        let (out, event) = com.accumulate_output_until_action().unwrap();
+        assert_eq!(out, vec![109]);
+        assert_eq!(
+            event,
+            StopEvent::Watchpoint(Record {
+                ip: 8,
+                access: Access::Write,
+                addr: 101,
+                value: 0
+            })
+        );
+
+        let mut outputs = out;
+        let mut hits = 1;
+        loop {
+            let (out, event) = com.accumulate_output_until_action().unwrap();
+            outputs.extend(out);
+            match event {
+                StopEvent::Watchpoint(_) => hits += 1,
+                StopEvent::Finished => break,
+                StopEvent::WaitingOnInput | StopEvent::LimitExceeded(_) => {
+                    panic!("not expecting {:?}", event)
+                }
+            }
+        }
+        assert_eq!(hits, 16);
+        assert_eq!(outputs.len(), 16);
+    }
+}
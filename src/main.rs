@@ -1,5 +1,6 @@
 mod days;
 mod intcode;
+mod tools;
 
 use clap::Clap;
 
@@ -20,6 +21,19 @@ enum SubCommand {
     Day7(days::Day7),
     Day8(days::Day8),
     Day9(days::Day9),
+    Disasm(tools::Disasm),
+    Cfg(tools::Graph),
+    Asm(tools::Asm),
+    Debug(tools::Debug),
+    Run(tools::Run),
+    Ascii(tools::Ascii),
+    Trace(tools::Trace),
+    Replay(tools::Replay),
+    Bench(tools::Bench),
+    Net(tools::Net),
+    Fuzz(tools::Fuzz),
+    Solve(tools::Solve),
+    Diff(tools::Diff),
 }
 
 fn main() {
@@ -34,5 +48,18 @@ fn main() {
         SubCommand::Day7(d) => d.run(),
         SubCommand::Day8(d) => d.run(),
         SubCommand::Day9(d) => d.run(),
+        SubCommand::Disasm(d) => d.run(),
+        SubCommand::Cfg(d) => d.run(),
+        SubCommand::Asm(d) => d.run(),
+        SubCommand::Debug(d) => d.run(),
+        SubCommand::Run(d) => d.run(),
+        SubCommand::Ascii(d) => d.run(),
+        SubCommand::Trace(d) => d.run(),
+        SubCommand::Replay(d) => d.run(),
+        SubCommand::Bench(d) => d.run(),
+        SubCommand::Net(d) => d.run(),
+        SubCommand::Fuzz(d) => d.run(),
+        SubCommand::Solve(d) => d.run(),
+        SubCommand::Diff(d) => d.run(),
     }
 }
@@ -0,0 +1,659 @@
+use clap::Clap;
+use num_bigint::BigInt;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt, fs,
+    io::{self, BufRead, Write},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    days::{day2, day9, IntcodeOpts, PatchOpts},
+    intcode::{
+        self, ascii, asm,
+        cfg::Cfg,
+        debugger, disasm,
+        fuzz::{self, Case, Rng},
+        limits::Limits,
+        network::{Network, NetworkStop},
+        patch,
+        reference::Stop,
+        replay::IoLog,
+        snapshot::Snapshot,
+        symbolic, Cell, HashMemory, IntcodeComputer, StopEvent,
+    },
+};
+
+fn parse_values(s: &Option<String>) -> Vec<isize> {
+    s.as_ref()
+        .map(|s| {
+            s.split(',')
+                .map(|v| v.trim().parse::<isize>().expect("cannot parse input value"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Clap)]
+pub struct Disasm {
+    input: String,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+impl Disasm {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        self.patch.apply(&mut com);
+
+        disasm::disassemble(&com.memory())
+            .iter()
+            .for_each(|line| println!("{}", line));
+    }
+}
+
+#[derive(Clap)]
+pub struct Graph {
+    input: String,
+    /// write the Graphviz DOT here instead of stdout
+    #[clap(short = 'o', long = "output")]
+    output: Option<String>,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+impl Graph {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        self.patch.apply(&mut com);
+
+        let cfg = Cfg::build(&com.memory());
+        match &self.output {
+            Some(path) => cfg.write_dot(fs::File::create(path).expect("error creating file")),
+            None => cfg.write_dot(io::stdout().lock()),
+        }
+        .expect("error writing graph");
+    }
+}
+
+#[derive(Clap)]
+pub struct Asm {
+    input: String,
+    #[clap(short = 'o', long = "output")]
+    output: Option<String>,
+}
+
+impl Asm {
+    pub fn run(&self) {
+        let src = fs::read_to_string(&self.input).expect("error reading file");
+        let prog = asm::assemble(&src).unwrap_or_else(|e| panic!("{}", e));
+        let out = patch::join(&prog);
+
+        match &self.output {
+            Some(path) => fs::write(path, out).expect("error writing file"),
+            None => println!("{}", out),
+        }
+    }
+}
+
+#[derive(Clap)]
+pub struct Debug {
+    input: String,
+    /// comma-separated values to queue as program input
+    #[clap(short = 'i', long = "inputs")]
+    inputs: Option<String>,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+impl Debug {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        self.patch.apply(&mut com);
+        let inputs = parse_values(&self.inputs);
+
+        let stdin = io::stdin();
+        debugger::Debugger::new(com, inputs)
+            .run(stdin.lock(), io::stdout())
+            .expect("error running debugger");
+    }
+}
+
+#[derive(Clap)]
+pub struct Run {
+    /// program to start from, unless resuming with --load
+    input: Option<String>,
+    /// comma-separated values to queue as program input
+    #[clap(short = 'i', long = "inputs")]
+    inputs: Option<String>,
+    /// resume from a snapshot instead of a fresh program
+    #[clap(long = "load")]
+    load: Option<String>,
+    /// save the machine here once it halts or waits on input (.json for JSON)
+    #[clap(long = "save")]
+    save: Option<String>,
+    /// memory cell type, snapshots need isize
+    #[clap(
+        long = "cells",
+        default_value = "isize",
+        possible_values = &["isize", "i128", "big"]
+    )]
+    cells: String,
+    /// fail when add or mul overflows a cell instead of wrapping around
+    #[clap(long = "checked")]
+    checked: bool,
+    #[clap(flatten)]
+    intcode: IntcodeOpts,
+}
+
+impl Run {
+    pub fn run(&self) {
+        match self.cells.as_str() {
+            "isize" => self.run_snapshot(),
+            "i128" => self.run_wide::<i128>(),
+            "big" => self.run_wide::<BigInt>(),
+            _ => unreachable!("clap checks the cell type"),
+        }
+    }
+
+    fn run_snapshot(&self) {
+        let mut snap = match (&self.load, &self.input) {
+            (Some(path), _) => Snapshot::load(path).expect("error loading snapshot"),
+            (None, Some(path)) => {
+                let f = fs::read_to_string(path).expect("error reading file");
+                Snapshot::new(day2::parse_input(f).expect("error parsing input"))
+            }
+            (None, None) => panic!("needs a program or --load"),
+        };
+        snap.inputs.extend(parse_values(&self.inputs));
+        snap.machine.check_overflow(self.checked);
+        let attached = self.intcode.apply(&mut snap.machine);
+
+        let start = snap.outputs.len();
+        let event = snap
+            .machine
+            .run(&mut snap.inputs, &mut snap.outputs)
+            .expect("error running program");
+        snap.outputs[start..].iter().for_each(|v| println!("{}", v));
+        match event {
+            StopEvent::WaitingOnInput => eprintln!("waiting on input"),
+            StopEvent::LimitExceeded(l) => eprintln!("stopped: {}", l),
+            StopEvent::Finished | StopEvent::Watchpoint(_) => {}
+        }
+
+        if let Some(path) = &self.save {
+            snap.save(path).expect("error saving snapshot");
+        }
+        attached.finish(snap.machine, io::stdout());
+    }
+
+    fn run_wide<T: Cell>(&self) {
+        if self.load.is_some() || self.save.is_some() {
+            panic!("snapshots only support isize cells");
+        }
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<T>()
+                .unwrap_or_else(|_| panic!("cannot parse value {}", v))
+        };
+        let path = self.input.as_ref().expect("needs a program");
+        let f = fs::read_to_string(path).expect("error reading file");
+        let mut com = intcode::from_cells(f.split(',').map(parse).collect());
+        com.check_overflow(self.checked);
+        let attached = self.intcode.apply(&mut com);
+        let mut inputs = self
+            .inputs
+            .iter()
+            .flat_map(|s| s.split(','))
+            .map(parse)
+            .collect::<VecDeque<_>>();
+
+        let event = com
+            .run(&mut inputs, &mut |v: T| println!("{}", v))
+            .expect("error running program");
+        match event {
+            StopEvent::WaitingOnInput => eprintln!("waiting on input"),
+            StopEvent::LimitExceeded(l) => eprintln!("stopped: {}", l),
+            StopEvent::Finished | StopEvent::Watchpoint(_) => {}
+        }
+        attached.finish(com, io::stdout());
+    }
+}
+
+#[derive(Clap)]
+pub struct Ascii {
+    input: String,
+    #[clap(flatten)]
+    intcode: IntcodeOpts,
+}
+
+impl Ascii {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        let attached = self.intcode.apply(&mut com);
+
+        let mut ascii = ascii::Ascii::new(com);
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            let (out, event) = ascii.run().expect("error running program");
+            out.iter().for_each(|c| print!("{}", c));
+            io::stdout().flush().expect("error writing output");
+            match event {
+                StopEvent::WaitingOnInput => match lines.next() {
+                    Some(line) => ascii.send_line(&line.expect("error reading input")),
+                    None => break,
+                },
+                StopEvent::LimitExceeded(l) => {
+                    eprintln!("stopped: {}", l);
+                    break;
+                }
+                StopEvent::Finished | StopEvent::Watchpoint(_) => break,
+            }
+        }
+        attached.finish(ascii.into_machine(), io::stdout());
+    }
+}
+
+#[derive(Clap)]
+pub struct Replay {
+    /// the program as it was loaded when recording
+    input: String,
+    /// the file written with --record
+    log: String,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+impl Replay {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        self.patch.apply(&mut com);
+        let src = fs::read_to_string(&self.log).expect("error reading file");
+        let log = IoLog::parse(&src).unwrap_or_else(|e| panic!("{}", e));
+
+        match log.replay(&com).expect("error replaying") {
+            None => println!("all {} machine(s) behave as recorded", log.machines.len()),
+            Some(d) => println!("first divergence: {}", d),
+        }
+    }
+}
+
+#[derive(Clap)]
+pub struct Trace {
+    input: String,
+    /// comma-separated values to feed as program input
+    #[clap(short = 'i', long = "inputs")]
+    inputs: Option<String>,
+    /// stop once `addr` or `start-end` is touched, may be repeated
+    #[clap(short = 'w', long = "watch")]
+    watch: Vec<String>,
+    /// write the JSON lines trace here instead of stdout
+    #[clap(short = 'o', long = "output")]
+    output: Option<String>,
+    #[clap(flatten)]
+    intcode: IntcodeOpts,
+}
+
+impl Trace {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        let mut inputs = VecDeque::from(parse_values(&self.inputs));
+        let attached = self.intcode.apply(&mut com);
+
+        let tracer = com.enable_tracing();
+        self.watch.iter().for_each(|w| {
+            let mut bounds = w
+                .splitn(2, '-')
+                .map(|b| b.parse::<usize>().expect("cannot parse watch address"));
+            let start = bounds.next().expect("empty watch range");
+            tracer.watch(start..=bounds.next().unwrap_or(start), None);
+        });
+
+        let event = com
+            .run(&mut inputs, &mut |v| eprintln!("output: {}", v))
+            .expect("error running program");
+        match event {
+            StopEvent::Finished => {}
+            StopEvent::WaitingOnInput => eprintln!("waiting on input, stopping"),
+            StopEvent::Watchpoint(hit) => eprintln!("watchpoint: {}", hit),
+            StopEvent::LimitExceeded(l) => eprintln!("stopped: {}", l),
+        }
+
+        let tracer = com.tracer().expect("tracing is enabled");
+        match &self.output {
+            Some(path) => {
+                tracer.write_json_lines(fs::File::create(path).expect("error creating file"))
+            }
+            None => tracer.write_json_lines(io::stdout().lock()),
+        }
+        .expect("error writing trace");
+        // stdout may carry the trace, keep the profile apart from it
+        attached.finish(com, io::stderr());
+    }
+}
+
+#[derive(Clap)]
+pub struct Bench {
+    /// how many times to run each case
+    #[clap(short = 'n', long = "iterations", default_value = "10")]
+    iterations: usize,
+    #[clap(long = "d2", default_value = "inputs/d2")]
+    d2: String,
+    #[clap(long = "d9", default_value = "inputs/d9")]
+    d9: String,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+impl Bench {
+    pub fn run(&self) {
+        let load = |path: &str| {
+            let f = fs::read_to_string(path).expect("error reading file");
+            let mut com = day2::parse_input(f).expect("error parsing input");
+            self.patch.apply(&mut com);
+            com
+        };
+        let d2 = load(&self.d2);
+        let d9 = load(&self.d9);
+
+        self.compare(
+            "day2 part 2",
+            &d2,
+            |com| day2::solve_part_2(&mut com.clone()).expect("error solving day 2"),
+            |com| day2::solve_part_2(&mut com.clone()).expect("error solving day 2"),
+        );
+        self.compare(
+            "day9 part 1",
+            &d9,
+            |com| day9::solve_part_1(com).expect("error solving day 9"),
+            |com| day9::solve_part_1(com).expect("error solving day 9"),
+        );
+        self.compare(
+            "day9 part 2",
+            &d9,
+            |com| day9::solve_part_2(com).expect("error solving day 9"),
+            |com| day9::solve_part_2(com).expect("error solving day 9"),
+        );
+    }
+
+    /// Times `f` as machines normally run, then with decoding on every step,
+    /// and `g`, the same on a copy keeping memory in a `HashMemory`, checking
+    /// that all give the same result.
+    fn compare<R, F, G>(&self, name: &str, com: &IntcodeComputer, f: F, g: G)
+    where
+        R: PartialEq + fmt::Debug,
+        F: Fn(&IntcodeComputer) -> R,
+        G: Fn(&IntcodeComputer<isize, HashMemory>) -> R,
+    {
+        let mut decoding = com.clone();
+        decoding.use_op_cache(false);
+        let hashed = com.with_store::<HashMemory>();
+
+        let (fast, expected) = self.time(&format!("{} cached", name), || f(com));
+        let (slow, actual) = self.time(&format!("{} decoding", name), || f(&decoding));
+        assert_eq!(expected, actual, "results differ without the op cache");
+        println!(
+            "{:<24} {:.2}x faster cached",
+            "",
+            slow.as_secs_f64() / fast.as_secs_f64()
+        );
+        let (slow, actual) = self.time(&format!("{} hashmap", name), || g(&hashed));
+        assert_eq!(expected, actual, "results differ with hashed memory");
+        println!(
+            "{:<24} {:.2}x faster paged",
+            "",
+            slow.as_secs_f64() / fast.as_secs_f64()
+        );
+    }
+
+    /// prints timings of `f` and returns the median and the last result
+    fn time<R, F: FnMut() -> R>(&self, name: &str, mut f: F) -> (Duration, R) {
+        let mut result = None;
+        let mut runs = (0..self.iterations.max(1))
+            .map(|_| {
+                let start = Instant::now();
+                result = Some(f());
+                start.elapsed()
+            })
+            .collect::<Vec<_>>();
+        runs.sort();
+
+        let total: Duration = runs.iter().sum();
+        let median = runs[runs.len() / 2];
+        println!(
+            "{:<24} min {:>10.3?}  median {:>10.3?}  mean {:>10.3?}",
+            name,
+            runs[0],
+            median,
+            total / runs.len() as u32
+        );
+        (median, result.expect("ran at least once"))
+    }
+}
+
+#[derive(Clap)]
+pub struct Net {
+    input: String,
+    /// how many copies of the program to connect
+    #[clap(short = 'n', long = "nodes", default_value = "50")]
+    nodes: usize,
+    /// router, pipeline, ring or broadcast (from machine 0)
+    #[clap(short = 't', long = "topology", default_value = "router")]
+    topology: String,
+    /// address whose packets the router holds back and replays to machine 0 when idle
+    #[clap(long = "nat")]
+    nat: Option<isize>,
+    /// comma-separated values queued for machine 0
+    #[clap(short = 'i', long = "inputs")]
+    inputs: Option<String>,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+impl Net {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        self.patch.apply(&mut com);
+        let machines = vec![com; self.nodes];
+
+        let mut net = match self.topology.as_str() {
+            "router" => Network::router(machines, self.nat),
+            "pipeline" => Network::pipeline(machines),
+            "ring" => Network::ring(machines),
+            "broadcast" => Network::broadcast(machines, 0),
+            t => panic!("unknown topology {}", t),
+        };
+        parse_values(&self.inputs)
+            .into_iter()
+            .for_each(|v| net.send(0, v));
+
+        let mut last_wake = None;
+        let stop = loop {
+            let stop = net.run().expect("error running network");
+            if stop != NetworkStop::Idle {
+                break stop;
+            }
+            // stop once the NAT has nothing new to wake the network with
+            match net.wake_from_nat() {
+                Some(packet) if last_wake != Some(packet) => {
+                    println!("NAT sends {:?} to 0", packet);
+                    last_wake = Some(packet);
+                }
+                _ => break stop,
+            }
+        };
+
+        println!("stopped: {:?}", stop);
+        if let Some(packet) = net.nat_packet() {
+            println!("last packet at the NAT: {:?}", packet);
+        }
+        (0..self.nodes)
+            .filter(|i| !net.outputs(*i).is_empty())
+            .for_each(|i| println!("{:>3}: {:?}", i, net.outputs(i)));
+    }
+}
+
+#[derive(Clap)]
+pub struct Fuzz {
+    /// seed for the whole run, taken from the clock if not given
+    #[clap(short = 's', long = "seed")]
+    seed: Option<u64>,
+    /// how many programs to try
+    #[clap(short = 'n', long = "cases", default_value = "10000")]
+    cases: usize,
+    /// cells of code per program
+    #[clap(short = 'l', long = "len", default_value = "40")]
+    len: usize,
+    #[clap(long = "max-steps", default_value = "10000")]
+    max_steps: usize,
+}
+
+impl Fuzz {
+    pub fn run(&self) {
+        let seed = self.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("clock before 1970")
+                .as_nanos() as u64
+        });
+        println!("seed {}", seed);
+
+        let mut rng = Rng::new(seed);
+        let mut stops = BTreeMap::new();
+        let mut steps = 0;
+        for i in 0..self.cases {
+            let case = Case::generate(rng.next(), self.len);
+            match fuzz::check(&case.program, &case.inputs, self.max_steps) {
+                Ok(outcome) => {
+                    let kind = match outcome.stop {
+                        Stop::Halted => "halted",
+                        Stop::NeedsInput => "waiting on input",
+                        Stop::Fault { .. } => "faulted",
+                        Stop::StepLimit => "still running",
+                    };
+                    *stops.entry(kind).or_insert(0) += 1;
+                    steps += outcome.steps;
+                }
+                Err(d) => {
+                    println!("case {} (seed {}) diverges: {}", i, case.seed, d);
+                    println!("program: {}", patch::join(&case.program));
+                    println!("inputs: {}", patch::join(&case.inputs));
+                    return;
+                }
+            }
+        }
+        println!(
+            "{} cases, no divergence, {:.1} instructions each on average",
+            self.cases,
+            steps as f64 / self.cases as f64
+        );
+        stops
+            .iter()
+            .for_each(|(kind, n)| println!("{:>8} {}", n, kind));
+    }
+}
+
+#[derive(Clap)]
+pub struct Solve {
+    input: String,
+    /// addresses of the cells to leave unknown
+    #[clap(short = 'v', long = "vars", default_value = "1,2")]
+    vars: String,
+    /// the cell to show, and to solve for with `--target`
+    #[clap(short = 'c', long = "cell", default_value = "0")]
+    cell: usize,
+    /// the value the cell should end up with
+    #[clap(short = 't', long = "target")]
+    target: Option<isize>,
+    /// values the unknown cells can take, from 0 up to this one excluded
+    #[clap(long = "below", default_value = "100")]
+    below: isize,
+    #[clap(flatten)]
+    patch: PatchOpts,
+}
+
+impl Solve {
+    pub fn run(&self) {
+        let f = fs::read_to_string(&self.input).expect("error reading file");
+        let mut com = day2::parse_input(f).expect("error parsing input");
+        self.patch.apply(&mut com);
+        let vars = parse_values(&Some(self.vars.clone()))
+            .iter()
+            .map(|v| *v as usize)
+            .collect::<Vec<_>>();
+
+        let limits = Limits {
+            max_steps: Some(1_000_000),
+            ..Limits::default()
+        };
+        let out = symbolic::run(&com.segments(), &vars, com.dialect(), &limits)
+            .expect("cannot run symbolically");
+        let cell = out.cell(self.cell);
+        println!("[{}] = {}", self.cell, cell);
+        out.outputs
+            .iter()
+            .enumerate()
+            .for_each(|(i, e)| println!("output {}: {}", i, e));
+
+        if let Some(target) = self.target {
+            let linear = cell
+                .linear()
+                .expect("not a linear function of the variables");
+            match linear.solve(&vars, 0..self.below, target) {
+                Some(values) => vars
+                    .iter()
+                    .zip(values)
+                    .for_each(|(addr, v)| println!("[{}] = {}", addr, v)),
+                None => println!("no solution"),
+            }
+        }
+    }
+}
+
+#[derive(Clap)]
+pub struct Diff {
+    /// program or snapshot to compare from
+    old: String,
+    /// program or snapshot to compare to
+    new: String,
+    /// unchanged disassembly lines to show around each change
+    #[clap(short = 'c', long = "context", default_value = "2")]
+    context: usize,
+    /// also write the changes as a patch file, for --patch
+    #[clap(short = 'o', long = "output")]
+    output: Option<String>,
+}
+
+impl Diff {
+    pub fn run(&self) {
+        let (old, new) = (memory_image(&self.old), memory_image(&self.new));
+        patch::write_diff(&old, &new, self.context, io::stdout().lock())
+            .expect("error writing diff");
+        if let Some(path) = &self.output {
+            fs::write(path, patch::diff(&old, &new).to_string()).expect("error writing file");
+        }
+    }
+}
+
+/// the memory of a program file, or of the machine in a snapshot, in
+/// segments so that a write far out is not copied up to
+fn memory_image(path: &str) -> Vec<(usize, Vec<isize>)> {
+    let data = fs::read(path).expect("error reading file");
+    match String::from_utf8(data) {
+        Ok(text) if !text.trim_start().starts_with('{') => day2::parse_input(text)
+            .expect("error parsing input")
+            .segments(),
+        _ => Snapshot::load(path)
+            .expect("error loading snapshot")
+            .machine
+            .segments(),
+    }
+}